@@ -0,0 +1,31 @@
+use std::process::Command;
+
+
+// Records build details for --build-info: the git commit, the compiler version and the target.
+fn main() {
+    let commit: String = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let rustc: String = command_output(&std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()), &["--version"]);
+    let target: String = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+
+    println!("cargo:rustc-env=QR_GEN_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=QR_GEN_RUSTC_VERSION={}", rustc);
+    println!("cargo:rustc-env=QR_GEN_TARGET={}", target);
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
+
+
+// Runs a command and returns its trimmed output, or "unknown" if it fails
+// (e.g. when building from a source archive without git).
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,27 @@
+// Build details shown by --build-info in both binaries, to help triage bug reports.
+
+
+// Optional features this binary was compiled with.
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("tui", cfg!(feature = "tui")),
+        ("server", cfg!(feature = "server")),
+        ("avif", cfg!(feature = "avif")),
+        ("tracing", cfg!(feature = "tracing")),
+    ]
+    .into_iter()
+    .filter(|&(_, enabled)| enabled)
+    .map(|(name, _)| name)
+    .collect()
+}
+
+
+// Prints the build details as a key/value block.
+pub fn print() {
+    let features: Vec<&str> = enabled_features();
+    println!("version:  {}", env!("CARGO_PKG_VERSION"));
+    println!("commit:   {}", env!("QR_GEN_GIT_COMMIT"));
+    println!("rustc:    {}", env!("QR_GEN_RUSTC_VERSION"));
+    println!("target:   {}", env!("QR_GEN_TARGET"));
+    println!("features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") });
+}
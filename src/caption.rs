@@ -0,0 +1,226 @@
+use ab_glyph::{Font, FontArc, GlyphId, OutlineCurve, PxScale, ScaleFont, point};
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::{Cli, svg_number};
+
+// Fonts tried when no --font is given, so captions work out of the box on common systems.
+const SYSTEM_FONTS: [&str; 7] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+// Font size in modules when no --font-size is given.
+const DEFAULT_SIZE: f64 = 2.0;
+
+
+// A line of text centered under the code. The glyphs are drawn from the font's outlines
+// in every output type, so SVG and PDF captions look like the PNG ones without the font installed.
+pub struct Caption {
+    font: FontArc,
+    text: String,
+    // Font size in pixels or points; without one it follows the module size
+    size: Option<f64>,
+}
+
+// A drawing command of a glyph outline, in output coordinates with y growing downwards.
+enum PathOp {
+    Move(f64, f64),
+    Line(f64, f64),
+    Cubic(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+impl Caption {
+    // Returns the caption asked for with --caption, or none. A font that can't be loaded is fatal.
+    pub fn from_args(args: &Cli) -> Option<Caption> {
+        let text: &str = args.caption.as_deref()?;
+        Some(Caption::new(text, args.font.as_deref(), args.font_size).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }))
+    }
+
+    // Returns a caption in the given font file, or the first system font found.
+    pub fn new(text: &str, font: Option<&Path>, size: Option<f64>) -> Result<Caption, String> {
+        Ok(Caption { font: load_font(font)?, text: text.to_string(), size })
+    }
+
+    // Returns the font size for a code `width` wide at `scale` units per module,
+    // shrunk when the text would be wider than the code.
+    fn fitted_size(&self, width: f64, scale: f64) -> f64 {
+        let size: f64 = self.size.unwrap_or(DEFAULT_SIZE * scale);
+        let text_width: f64 = self.layout(size).last().map_or(0.0, |&(_, x, advance)| x + advance);
+        if text_width > width { size * width / text_width } else { size }
+    }
+
+    // Returns the glyphs of the text at the given size, each with its offset from the start of the line and its advance.
+    fn layout(&self, size: f64) -> Vec<(GlyphId, f64, f64)> {
+        let scaled = self.font.as_scaled(self.px_scale(size));
+        let mut caret: f64 = 0.0;
+        let mut previous: Option<GlyphId> = None;
+        let mut glyphs: Vec<(GlyphId, f64, f64)> = Vec::new();
+        for c in self.text.chars().filter(|c| !c.is_control()) {
+            let id: GlyphId = self.font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id) as f64;
+            }
+            let advance: f64 = scaled.h_advance(id) as f64;
+            glyphs.push((id, caret, advance));
+            caret += advance;
+            previous = Some(id);
+        }
+        glyphs
+    }
+
+    // The font size is the em size, as in CSS and PDF, while ab_glyph scales by the line height.
+    fn px_scale(&self, size: f64) -> PxScale {
+        let units_per_em: f32 = self.font.units_per_em().unwrap_or(1000.0);
+        PxScale::from(size as f32 * self.font.height_unscaled() / units_per_em)
+    }
+
+    // Returns the height of a line of the caption in a band `width` wide.
+    pub fn line_height(&self, width: f64, scale: f64) -> f64 {
+        let scaled = self.font.as_scaled(self.px_scale(self.fitted_size(width, scale)));
+        (scaled.ascent() - scaled.descent()) as f64
+    }
+
+    // Returns the height of the band below the code that holds the caption: a line of text
+    // and half the font size of space under it.
+    pub fn band_height(&self, width: f64, scale: f64) -> f64 {
+        self.line_height(width, scale) + self.fitted_size(width, scale) / 2.0
+    }
+
+    // Returns the outlines of the caption centered in a band `width` wide whose top is at (left, top).
+    fn path(&self, left: f64, top: f64, width: f64, scale: f64) -> Vec<PathOp> {
+        let size: f64 = self.fitted_size(width, scale);
+        let glyphs: Vec<(GlyphId, f64, f64)> = self.layout(size);
+        let text_width: f64 = glyphs.last().map_or(0.0, |&(_, x, advance)| x + advance);
+        let start: f64 = left + (width - text_width) / 2.0;
+        let baseline: f64 = top + self.font.as_scaled(self.px_scale(size)).ascent() as f64;
+        // Outlines are in font units with y growing upwards
+        let units: f64 = size / self.font.units_per_em().unwrap_or(1000.0) as f64;
+
+        let mut ops: Vec<PathOp> = Vec::new();
+        for (id, x, _) in glyphs {
+            let Some(outline) = self.font.outline(id) else { continue };
+            let at = |p: ab_glyph::Point| (start + x + p.x as f64 * units, baseline - p.y as f64 * units);
+            let mut end: Option<ab_glyph::Point> = None;
+            for curve in outline.curves {
+                let (from, to) = match curve {
+                    OutlineCurve::Line(from, to) | OutlineCurve::Quad(from, _, to) | OutlineCurve::Cubic(from, _, _, to) => (from, to),
+                };
+                // A curve that doesn't continue the last one starts a new contour
+                if end != Some(from) {
+                    if end.is_some() {
+                        ops.push(PathOp::Close);
+                    }
+                    let (x, y) = at(from);
+                    ops.push(PathOp::Move(x, y));
+                }
+                ops.push(match curve {
+                    OutlineCurve::Line(_, to) => {
+                        let (x, y) = at(to);
+                        PathOp::Line(x, y)
+                    }
+                    // PDF has no quadratic curves, so they are raised to cubic ones
+                    OutlineCurve::Quad(from, control, to) => {
+                        let (x1, y1) = at(point(from.x + (control.x - from.x) * 2.0 / 3.0, from.y + (control.y - from.y) * 2.0 / 3.0));
+                        let (x2, y2) = at(point(to.x + (control.x - to.x) * 2.0 / 3.0, to.y + (control.y - to.y) * 2.0 / 3.0));
+                        let (x, y) = at(to);
+                        PathOp::Cubic(x1, y1, x2, y2, x, y)
+                    }
+                    OutlineCurve::Cubic(_, control1, control2, to) => {
+                        let ((x1, y1), (x2, y2), (x, y)) = (at(control1), at(control2), at(to));
+                        PathOp::Cubic(x1, y1, x2, y2, x, y)
+                    }
+                });
+                end = Some(to);
+            }
+            if end.is_some() {
+                ops.push(PathOp::Close);
+            }
+        }
+        ops
+    }
+
+    // Returns SVG path data for the caption in a band `width` wide with its top-left corner at (left, top).
+    pub fn svg_path(&self, left: f64, top: f64, width: f64, scale: f64) -> String {
+        self.path(left, top, width, scale)
+            .into_iter()
+            .map(|op| match op {
+                PathOp::Move(x, y) => format!("M{},{}", svg_number(x), svg_number(y)),
+                PathOp::Line(x, y) => format!("L{},{}", svg_number(x), svg_number(y)),
+                PathOp::Cubic(x1, y1, x2, y2, x, y) => format!("C{},{} {},{} {},{}",
+                    svg_number(x1), svg_number(y1), svg_number(x2), svg_number(y2), svg_number(x), svg_number(y)),
+                PathOp::Close => "Z".to_string(),
+            })
+            .collect()
+    }
+
+    // Returns PDF operators that fill the caption, with the band measured from the top of a page of the given height.
+    pub fn pdf_content(&self, left: f64, top: f64, width: f64, scale: f64, page_height: f64) -> String {
+        let mut content: String = self.path(left, top, width, scale)
+            .into_iter()
+            .map(|op| match op {
+                // PDF measures y upwards from the bottom of the page
+                PathOp::Move(x, y) => format!("{} {} m\n", svg_number(x), svg_number(page_height - y)),
+                PathOp::Line(x, y) => format!("{} {} l\n", svg_number(x), svg_number(page_height - y)),
+                PathOp::Cubic(x1, y1, x2, y2, x, y) => format!("{} {} {} {} {} {} c\n",
+                    svg_number(x1), svg_number(page_height - y1), svg_number(x2), svg_number(page_height - y2), svg_number(x), svg_number(page_height - y)),
+                PathOp::Close => "h\n".to_string(),
+            })
+            .collect();
+        content += "f\n";
+        content
+    }
+
+    // Draws the caption antialiased into the image, in a band `width` wide with its top-left corner at (left, top).
+    pub fn draw(&self, image: &mut RgbaImage, left: f64, top: f64, width: f64, scale: f64, color: Rgba<u8>) {
+        let size: f64 = self.fitted_size(width, scale);
+        let glyphs: Vec<(GlyphId, f64, f64)> = self.layout(size);
+        let text_width: f64 = glyphs.last().map_or(0.0, |&(_, x, advance)| x + advance);
+        let start: f64 = left + (width - text_width) / 2.0;
+        let baseline: f64 = top + self.font.as_scaled(self.px_scale(size)).ascent() as f64;
+
+        for (id, x, _) in glyphs {
+            let glyph = id.with_scale_and_position(self.px_scale(size), point((start + x) as f32, baseline as f32));
+            let Some(outlined) = self.font.outline_glyph(glyph) else { continue };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let (px, py) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+                if px < 0 || py < 0 || px >= image.width() as i64 || py >= image.height() as i64 {
+                    return;
+                }
+                // Composite the text color over what is there, weighted by the coverage of the pixel
+                let pixel: &mut Rgba<u8> = image.get_pixel_mut(px as u32, py as u32);
+                let alpha: f32 = coverage.clamp(0.0, 1.0) * color.0[3] as f32 / 255.0;
+                let below: f32 = pixel.0[3] as f32 / 255.0 * (1.0 - alpha);
+                let out: f32 = alpha + below;
+                if out > 0.0 {
+                    for channel in 0..3 {
+                        pixel.0[channel] = ((color.0[channel] as f32 * alpha + pixel.0[channel] as f32 * below) / out).round() as u8;
+                    }
+                }
+                pixel.0[3] = (out * 255.0).round() as u8;
+            });
+        }
+    }
+}
+
+
+// Loads the font from the given TrueType or OpenType file, or else the first system font found.
+fn load_font(path: Option<&Path>) -> Result<FontArc, String> {
+    let path: &Path = match path {
+        Some(path) => path,
+        None => SYSTEM_FONTS.iter().map(Path::new).find(|path| path.exists())
+            .ok_or("No font found for the caption. Use --font with a TrueType or OpenType file.")?,
+    };
+    let data: Vec<u8> = std::fs::read(path).map_err(|e| format!("Failed to read font '{}': {}", path.display(), e))?;
+    FontArc::try_from_vec(data).map_err(|e| format!("Failed to load font '{}': {}", path.display(), e))
+}
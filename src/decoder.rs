@@ -1,60 +1,669 @@
-use std::{fs::File, io::{self, IsTerminal, Read}, path::PathBuf};
-use image::ImageReader;
+mod build_info;
+mod trace;
+
+use std::{fs::File, io::{self, IsTerminal, Read, Write}, path::{Path, PathBuf}};
+use image::{GrayImage, ImageFormat, ImageReader, Luma, imageops::FilterType};
 use clap::Parser;
 
+// Width of the light margin, in modules, drawn around normalized codes.
+const QUIET_ZONE: u32 = 4;
+
+// A detected code together with the result of decoding it.
+type ScannedCode = (quircs::Code, Result<quircs::Data, quircs::DecodeError>);
+
+// Global thresholds tried in turn by --auto-threshold, from dark to light.
+const THRESHOLDS: [u8; 3] = [96, 128, 160];
+
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Extract data from a QR code file or piped data")]
 struct Cli {
-    #[arg(short, long, value_name = "INPUT", help = "QR code image file or piped data.")]
-    input: Option<PathBuf>,
+    #[arg(short, long, value_name = "INPUT", num_args = 1.., help = "QR code image files or piped data. Several files are decoded in turn, e.g. to collect them with --csv-out, and a summary is printed at the end.")]
+    input: Vec<PathBuf>,
+
+    #[arg(long, help = "Read the image from stdin, even if it is a terminal. Takes precedence over --input.")]
+    stdin: bool,
+
+    #[arg(short, long, help = "Print the detected image format and dimensions to stderr.")]
+    verbose: bool,
+
+    #[arg(long, value_name = "BOOL", action = clap::ArgAction::Set, default_value_t = true, help = "If the plain image doesn't decode, retry with a few fixed black/white thresholds.")]
+    auto_threshold: bool,
+
+    #[arg(long, help = "Print every detected code instead of only the first one.")]
+    all: bool,
+
+    #[arg(long, value_name = "N", conflicts_with = "all", help = "Print only the Nth detected code, counting from 0.")]
+    index: Option<usize>,
+
+    #[arg(long, conflicts_with_all = ["hex", "split_on", "preview"], help = "Write the exact payload bytes to stdout instead of text.")]
+    raw: bool,
+
+    #[arg(long, conflicts_with_all = ["split_on", "preview"], help = "Print the payload bytes as hexadecimal instead of text.")]
+    hex: bool,
+
+    #[arg(long, value_name = "CSV_OUT", help = "Also write every decoded code to a CSV file.")]
+    csv_out: Option<PathBuf>,
+
+    #[arg(long, conflicts_with_all = ["raw", "hex", "split_on", "preview"], help = "Print one JSON object per image with its file name, format and dimensions, the number of detected codes and the selected ones, with \"count\":0 and \"codes\":[] when none are found. Several images end with a {\"summary\":...} object.")]
+    json: bool,
+
+    #[arg(long, value_name = "N", help = "Truncate printed payloads to N characters.")]
+    preview: Option<usize>,
+
+    #[arg(long, value_name = "DELIM", help = "Split payloads on a delimiter and print one field per line. Use \\ to escape it.")]
+    split_on: Option<String>,
+
+    #[arg(long, requires = "split_on", help = "Print the split fields as a JSON array instead.")]
+    split_json: bool,
+
+    #[arg(long, value_name = "PATH", help = "Save each detected code deskewed to a clean square PNG. Several codes get numbered files.")]
+    normalize: Option<PathBuf>,
+
+    #[arg(long, value_name = "SCALE", help = "Pixels per module for --normalize.", default_value_t = 1)]
+    normalize_scale: u32,
+
+    #[arg(long, help = "Overwrite existing files from --csv-out and --normalize. Otherwise an existing CSV file is an error and existing normalized codes are skipped.")]
+    force: bool,
+
+    #[arg(long, help = "Print the version, git commit, compiler, target and enabled features, then exit.")]
+    build_info: bool,
 }
 
 
 fn main() -> io::Result<()> {
+    trace::init();
     let args = Cli::parse();
 
-    // let myimage: Option<PathBuf> = Some(PathBuf::from("qrcode.png"));
-    // let myimage: Option<PathBuf> = None;
+    if args.build_info {
+        build_info::print();
+        return Ok(());
+    }
+
+    if args.split_on.as_deref() == Some("") {
+        eprintln!("The --split-on delimiter must not be empty.");
+        std::process::exit(1);
+    }
+
+    // Checked before any decoding, so a long batch doesn't fail at the very end
+    if let Some(csv_path) = &args.csv_out && let Err(e) = check_overwrite(csv_path, args.force) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    // An explicit --stdin reads one image from stdin; otherwise every --input is decoded in turn
+    let inputs: Vec<Option<PathBuf>> = if args.stdin || args.input.is_empty() {
+        vec![None]
+    } else {
+        args.input.iter().cloned().map(Some).collect()
+    };
+    let batch: bool = inputs.len() > 1;
+
+    let total: usize = inputs.len();
+    let mut csv_rows: Vec<String> = Vec::new();
+    let mut normalized: usize = 0;
+    // Selected codes that were decoded, over all images
+    let mut codes: usize = 0;
+    // Images that could not be read or have a selected code that could not be decoded
+    let mut failed: usize = 0;
+    let mut empty: usize = 0;
+    // Names of the images that were not decoded, for the batch summary
+    let mut failures: Vec<String> = Vec::new();
+
+    for input in inputs {
+        // Name used for the image in messages and CSV rows; "-" stands for piped data
+        let filename: String = input.as_ref().map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        match decode_image(input, &filename, &args, batch, &mut csv_rows, &mut normalized, &mut codes) {
+            Ok(Outcome::Decoded) => continue,
+            Ok(Outcome::Unreadable) => failed += 1,
+            Ok(Outcome::Empty) => empty += 1,
+            Err(e) if batch => {
+                eprintln!("Failed to read '{}': {}", filename, e);
+                failed += 1;
+            }
+            Err(e) => return Err(e),
+        }
+        failures.push(filename);
+    }
+
+    if batch {
+        let decoded: usize = total - failed - empty;
+        if args.json {
+            let names: Vec<String> = failures.iter().map(|name| json_string(name)).collect();
+            println!("{{\"summary\":{{\"total\":{},\"decoded\":{},\"failed\":{},\"empty\":{},\"codes\":{},\"failures\":[{}]}}}}",
+                total, decoded, failed, empty, codes, names.join(","));
+        } else {
+            eprintln!("Decoded {}/{} images ({} code{}); {} had no code and {} failed.", decoded, total, codes, if codes == 1 { "" } else { "s" }, empty, failed);
+            if args.verbose && !failures.is_empty() {
+                eprintln!("Not decoded: {}", failures.join(", "));
+            }
+        }
+    }
+
+    if let Some(csv_path) = &args.csv_out {
+        write_csv(csv_path, &csv_rows)?;
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    // Finding nothing is not the same as decoding an empty payload, so scripts get a distinct status
+    if empty > 0 {
+        std::process::exit(2);
+    }
+
+    Ok(())
+}
+
+
+// How decoding one image went.
+enum Outcome {
+    Decoded,
+    // A selected code was found but could not be read
+    Unreadable,
+    // No code was found
+    Empty,
+}
+
+
+// Decodes the codes in one image, prints the selected ones and adds every decoded code to the CSV rows.
+// In a batch, normalized codes are numbered across all images and messages name the image.
+// Every selected code that decodes is counted in `codes_decoded`.
+fn decode_image(input: Option<PathBuf>, filename: &str, args: &Cli, batch: bool, csv_rows: &mut Vec<String>, normalized: &mut usize, codes_decoded: &mut usize) -> io::Result<Outcome> {
+    let (img, format) = read_image(input, args.stdin)?;
+
+    let format_name: String = format.map(|f| format!("{:?}", f).to_uppercase()).unwrap_or_else(|| "unknown".to_string());
+    let (width, height) = (img.width(), img.height());
+    let prefix: String = if batch { format!("{}: ", filename) } else { String::new() };
+    if args.verbose {
+        eprintln!("{}Image format: {}", prefix, format_name);
+        eprintln!("{}Image dimensions: {}x{}", prefix, width, height);
+    }
+
+    let img_gray: GrayImage = flatten_to_luma(img);
+
+    let mut codes: Vec<ScannedCode> = scan(&img_gray);
+
+    // Marginal scans often decode once they are forced to pure black and white
+    let decoded_all = |codes: &[ScannedCode]| {
+        !codes.is_empty() && codes.iter().all(|(_, decoded)| decoded.is_ok())
+    };
+    if args.auto_threshold && !decoded_all(&codes) {
+        for threshold in THRESHOLDS {
+            let retry = scan(&binarize(&img_gray, threshold));
+            trace::debug!(threshold, codes = retry.len(), decoded = decoded_all(&retry), "threshold retry");
+            if decoded_all(&retry) {
+                if args.verbose {
+                    eprintln!("{}Decoded after thresholding at {}.", prefix, threshold);
+                }
+                codes = retry;
+                break;
+            }
+        }
+    }
+    let found: usize = codes.len();
+
+    if let Some(index) = args.index && found > 0 && index >= found {
+        eprintln!("{}Code index {} is out of range: found {} code{}.", prefix, index, found, if found == 1 { "" } else { "s" });
+        return Ok(Outcome::Unreadable);
+    }
+
+    // Selected codes that were found but could not be read
+    let mut unreadable: usize = 0;
+    let mut json_codes: Vec<String> = Vec::new();
+
+    for (index, (code, decoded)) in codes.into_iter().enumerate() {
+        // Saved before decoding so that damaged codes can still be archived
+        if let Some(path) = &args.normalize {
+            *normalized += 1;
+            let path: PathBuf = if found > 1 || batch { numbered_path(path, *normalized) } else { path.clone() };
+            match check_overwrite(&path, args.force) {
+                Ok(()) => normalize_code(&img_gray, &code, args.normalize_scale.max(1))
+                    .save(&path)
+                    .unwrap_or_else(|e| eprintln!("Failed to save normalized code '{}': {}", path.display(), e)),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        // Only the selected codes are printed; normalizing and CSV rows still cover every code
+        let selected: bool = args.all || index == args.index.unwrap_or(0);
 
-    let myimage: Option<PathBuf> = args.input;
+        // A damaged code is still saved above, and the loop goes on with the others.
+        // Only a selected one is an error; the others just have no CSV row.
+        let decoded: quircs::Data = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                if selected {
+                    eprintln!("{}Failed to decode code {}: {}.", prefix, index, e);
+                    unreadable += 1;
+                    json_codes.push(format!("{{\"index\":{},\"error\":{}}}", index, json_string(&e.to_string())));
+                }
+                continue;
+            }
+        };
+        // Invalid UTF-8 is replaced with U+FFFD so mostly-text payloads still print
+        let payload: String = String::from_utf8_lossy(&decoded.payload).into_owned();
+        let replaced: usize = decoded.payload.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+        if args.verbose && replaced > 0 {
+            eprintln!("{}Code {}: replaced {} invalid UTF-8 byte{}.", prefix, index, replaced, if replaced == 1 { "" } else { "s" });
+        }
 
-    let img = read_image(myimage)?;
+        if selected {
+            *codes_decoded += 1;
+            if args.json {
+                json_codes.push(format!("{{\"index\":{},\"payload\":{},\"version\":{},\"ecc\":\"{:?}\"}}",
+                    index, json_string(&payload), decoded.version, decoded.ecc_level));
+            } else if args.raw {
+                io::stdout().write_all(&decoded.payload)?;
+            } else if args.hex {
+                println!("{}", decoded.payload.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+            } else {
+                print_payload(&payload, args);
+            }
+        }
+
+        if args.csv_out.is_some() {
+            let corners: Vec<String> = code.corners.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+            let fields: [String; 6] = [
+                filename.to_string(),
+                index.to_string(),
+                payload.clone(),
+                decoded.version.to_string(),
+                format!("{:?}", decoded.ecc_level),
+                corners.join(";"),
+            ];
+            csv_rows.push(fields.iter().map(|f| csv_field(f)).collect::<Vec<String>>().join(","));
+        }
+    }
+
+    // Scripts get the object even when nothing was found, so a count of 0 tells it from an empty payload
+    if args.json {
+        println!("{{\"file\":{},\"format\":{},\"width\":{},\"height\":{},\"count\":{},\"codes\":[{}]}}",
+            json_string(filename), json_string(&format_name), width, height, found, json_codes.join(","));
+    }
+
+    if unreadable > 0 {
+        return Ok(Outcome::Unreadable);
+    }
+
+    if found == 0 {
+        eprintln!("No QR code found in {}.", if batch { format!("'{}'", filename) } else { "the image".to_string() });
+        return Ok(Outcome::Empty);
+    }
+
+    Ok(Outcome::Decoded)
+}
 
-    let img_gray = img.into_luma8();
 
-    // create a decoder
+// Converts the image to grayscale. Transparent pixels are composited over white first,
+// otherwise a transparent background turns black and hides the code.
+fn flatten_to_luma(img: image::DynamicImage) -> GrayImage {
+    if !img.color().has_alpha() {
+        return img.into_luma8();
+    }
+    let img = img.into_luma_alpha8();
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let [luma, alpha] = img.get_pixel(x, y).0.map(u32::from);
+        Luma([((luma * alpha + 255 * (255 - alpha)) / 255) as u8])
+    })
+}
+
+
+// Finds every code in the image and tries to decode it.
+// Codes whose grid can't be extracted are left out.
+fn scan(img: &GrayImage) -> Vec<ScannedCode> {
+    trace::stage!("scan", width = img.width(), height = img.height());
     let mut decoder = quircs::Quirc::default();
+    decoder.identify(img.width() as usize, img.height() as usize, img)
+        .filter_map(Result::ok)
+        .map(|code| {
+            let decoded = code.decode();
+            (code, decoded)
+        })
+        .collect()
+}
 
-    // identify all qr codes
-    let codes = decoder.identify(img_gray.width() as usize, img_gray.height() as usize, &img_gray);
 
-    for code in codes {
-        let code: quircs::Code = code.expect("failed to extract qr code");
-        let decoded: quircs::Data = code.decode().expect("failed to decode qr code");
-        println!("{}", std::str::from_utf8(&decoded.payload).unwrap());
+// Returns a black and white copy of the image: pixels darker than the threshold become black.
+fn binarize(img: &GrayImage, threshold: u8) -> GrayImage {
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([if img.get_pixel(x, y).0[0] < threshold { 0 } else { 255 }])
+    })
+}
+
+
+// Maps the unit square onto the quadrilateral of a detected code (a projective transform).
+struct Perspective {
+    a: f64, b: f64, c: f64,
+    d: f64, e: f64, f: f64,
+    g: f64, h: f64,
+}
+
+impl Perspective {
+    // Builds the transform from the code's corners, given clockwise from the top-left.
+    fn from_corners(corners: &[quircs::Point; 4]) -> Self {
+        let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = corners.map(|p| (p.x as f64, p.y as f64));
+        let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+        let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+
+        let (g, h) = if dx3 == 0.0 && dy3 == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let den: f64 = dx1 * dy2 - dx2 * dy1;
+            ((dx3 * dy2 - dx2 * dy3) / den, (dx1 * dy3 - dx3 * dy1) / den)
+        };
+
+        Perspective {
+            a: x1 - x0 + g * x1, b: x3 - x0 + h * x3, c: x0,
+            d: y1 - y0 + g * y1, e: y3 - y0 + h * y3, f: y0,
+            g, h,
+        }
+    }
+
+    // Returns the image position of the point (u, v) of the unit square.
+    fn map(&self, u: f64, v: f64) -> (f64, f64) {
+        let w: f64 = self.g * u + self.h * v + 1.0;
+        ((self.a * u + self.b * v + self.c) / w, (self.d * u + self.e * v + self.f) / w)
+    }
+}
+
+
+// Samples the center of every module through the code's perspective and redraws the
+// grid axis-aligned, with a quiet zone, at the given number of pixels per module.
+fn normalize_code(img: &GrayImage, code: &quircs::Code, scale: u32) -> GrayImage {
+    let size = code.size as u32;
+    let transform = Perspective::from_corners(&code.corners);
+
+    let mut samples: Vec<u8> = Vec::with_capacity((size * size) as usize);
+    for my in 0..size {
+        for mx in 0..size {
+            let (x, y) = transform.map((mx as f64 + 0.5) / size as f64, (my as f64 + 0.5) / size as f64);
+            let px = (x.round() as i64).clamp(0, img.width() as i64 - 1) as u32;
+            let py = (y.round() as i64).clamp(0, img.height() as i64 - 1) as u32;
+            samples.push(img.get_pixel(px, py).0[0]);
+        }
+    }
+
+    // Split dark from light halfway between the darkest and lightest samples
+    let min = *samples.iter().min().unwrap_or(&0) as u16;
+    let max = *samples.iter().max().unwrap_or(&255) as u16;
+    let threshold = ((min + max) / 2) as u8;
+
+    let img_size: u32 = size + 2 * QUIET_ZONE;
+    let mut grid: GrayImage = GrayImage::from_pixel(img_size, img_size, Luma([255u8]));
+    for (i, &sample) in samples.iter().enumerate() {
+        if sample < threshold {
+            grid.put_pixel(i as u32 % size + QUIET_ZONE, i as u32 / size + QUIET_ZONE, Luma([0u8]));
+        }
+    }
+
+    image::imageops::resize(&grid, img_size * scale, img_size * scale, FilterType::Nearest)
+}
+
+
+// Inserts a number before the file extension, e.g. code.png -> code-2.png.
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name: String = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    path.with_file_name(file_name)
+}
+
+
+// Prints a decoded payload, split into fields and truncated as requested.
+fn print_payload(payload: &str, args: &Cli) {
+    let fields: Vec<String> = match &args.split_on {
+        Some(delimiter) => split_fields(payload, delimiter),
+        None => vec![payload.to_string()],
+    };
+
+    if args.split_json {
+        println!("{}", json_array(&fields));
+        return;
+    }
+
+    for field in fields {
+        match args.preview {
+            Some(max_chars) => println!("{}", truncate_payload(&field, max_chars)),
+            None => println!("{}", field),
+        }
+    }
+}
+
+
+// Splits the payload on the delimiter, keeping empty fields.
+// A backslash escapes the delimiter or another backslash, so fields can contain them.
+fn split_fields(payload: &str, delimiter: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut rest: &str = payload;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            if let Some(after) = escaped.strip_prefix(delimiter) {
+                current.push_str(delimiter);
+                rest = after;
+                continue;
+            }
+            if let Some(after) = escaped.strip_prefix('\\') {
+                current.push('\\');
+                rest = after;
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix(delimiter) {
+            fields.push(std::mem::take(&mut current));
+            rest = after;
+            continue;
+        }
+        current.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    fields.push(current);
+    fields
+}
+
+
+// Returns the fields as a JSON array of strings, as --split-json prints them.
+fn json_array(fields: &[String]) -> String {
+    let items: Vec<String> = fields.iter().map(|f| json_string(f)).collect();
+    format!("[{}]", items.join(","))
+}
+
+
+// Returns the text as a quoted JSON string.
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            '\r' => result += "\\r",
+            '\t' => result += "\\t",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
     }
+    result.push('"');
+    result
+}
+
 
+// Shortens the payload to at most `max_chars` characters and marks the cut with an ellipsis.
+// Cutting on character boundaries keeps multi-byte UTF-8 sequences intact.
+fn truncate_payload(payload: &str, max_chars: usize) -> String {
+    match payload.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &payload[..end]),
+        None => payload.to_string(),
+    }
+}
+
+
+// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+// Refuses to replace an existing file unless overwriting was forced.
+fn check_overwrite(file_path: &Path, force: bool) -> Result<(), String> {
+    if !force && file_path.exists() {
+        return Err(format!("Skipped '{}': file already exists. Use --force to overwrite it.", file_path.display()));
+    }
+    Ok(())
+}
+
+
+// Writes the header and the given rows to a CSV file.
+fn write_csv(csv_path: &Path, rows: &[String]) -> io::Result<()> {
+    let mut file = File::create(csv_path)?;
+    writeln!(file, "filename,index,payload,version,ecc,corners")?;
+    for row in rows {
+        writeln!(file, "{}", row)?;
+    }
     Ok(())
 }
 
-fn read_image(input: Option<PathBuf>) -> io::Result<image::DynamicImage> {
+// Reads the image and returns it along with the guessed format.
+// An explicit --stdin wins over --input, which wins over data piped into the standard input.
+fn read_image(input: Option<PathBuf>, force_stdin: bool) -> io::Result<(image::DynamicImage, Option<ImageFormat>)> {
     let mut buffer: Vec<u8> = Vec::new();
 
-    if !io::stdin().is_terminal() {
+    if force_stdin {
         io::stdin().read_to_end(&mut buffer)?;
     } else if let Some(file_path) = input {
         File::open(file_path)?.read_to_end(&mut buffer)?;
+    } else if !io::stdin().is_terminal() {
+        io::stdin().read_to_end(&mut buffer)?;
     } else {
         eprintln!("No input provided. Please specify a file or pipe data.");
         std::process::exit(1);
     }
 
-    let img = ImageReader::new(io::Cursor::new(buffer))
-        .with_guessed_format()
-        .expect("Failed to guess image format")
-        .decode()
-        .expect("Failed to decode image");
+    let reader = match ImageReader::new(io::Cursor::new(buffer)).with_guessed_format() {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("Failed to guess the image format: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let format: Option<ImageFormat> = reader.format();
+    let img = match reader.decode() {
+        Ok(img) => img,
+        // Formats like AVIF are recognized but need an optional decoder
+        Err(image::ImageError::Unsupported(e)) => {
+            match format {
+                Some(format) => eprintln!("Cannot decode {} images: {}", format!("{:?}", format).to_uppercase(), e),
+                None => eprintln!("Cannot decode the image: {}", e),
+            }
+            if format == Some(ImageFormat::Avif) {
+                eprintln!("AVIF input needs qr-dec built with --features avif.");
+            }
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to decode the image: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    Ok((img, format))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads a fixture the way read_image does, checking that its format is recognized.
+    fn fixture(bytes: &[u8], format: ImageFormat) -> GrayImage {
+        assert_eq!(image::guess_format(bytes).unwrap(), format);
+        flatten_to_luma(image::load_from_memory(bytes).unwrap())
+    }
 
-    Ok(img)
+    // Returns the payloads of the scanned codes that decoded.
+    fn payloads(codes: Vec<ScannedCode>) -> Vec<Vec<u8>> {
+        codes.into_iter().filter_map(|(_, decoded)| decoded.ok()).map(|data| data.payload).collect()
+    }
+
+    #[test]
+    fn decodes_webp() {
+        let img: GrayImage = fixture(include_bytes!("../tests/fixtures/hello.webp"), ImageFormat::WebP);
+        assert_eq!(payloads(scan(&img)), [b"Hello WebP"]);
+    }
+
+    // Every other pixel is 40 levels lighter, so the light modules range down to 190 and the dark ones
+    // up to 130. Whether a plain scan reads it depends on the decoder's own binarization, so the test
+    // only asks that one of the fixed thresholds separates the modules.
+    #[test]
+    fn low_contrast_decodes_after_thresholding() {
+        let img: GrayImage = fixture(include_bytes!("../tests/fixtures/low-contrast.png"), ImageFormat::Png);
+        assert!(THRESHOLDS.into_iter().any(|threshold| payloads(scan(&binarize(&img, threshold))) == [b"Hello low contrast"]));
+    }
+
+    #[test]
+    fn split_keeps_trailing_and_empty_fields() {
+        assert_eq!(split_fields("a,b,", ","), ["a", "b", ""]);
+        assert_eq!(split_fields("a,,b", ","), ["a", "", "b"]);
+        assert_eq!(split_fields(",", ","), ["", ""]);
+        assert_eq!(split_fields("", ","), [""]);
+        assert_eq!(split_fields("no delimiter", ","), ["no delimiter"]);
+    }
+
+    #[test]
+    fn split_on_multi_byte_delimiters() {
+        assert_eq!(split_fields("a::b::c", "::"), ["a", "b", "c"]);
+        // A lone colon is not the delimiter
+        assert_eq!(split_fields("a:b::c", "::"), ["a:b", "c"]);
+        assert_eq!(split_fields("Grüße§straße§", "§"), ["Grüße", "straße", ""]);
+    }
+
+    #[test]
+    fn split_unescapes_delimiters_and_backslashes() {
+        assert_eq!(split_fields(r"a\,b,c", ","), ["a,b", "c"]);
+        assert_eq!(split_fields(r"a\\,b", ","), [r"a\", "b"]);
+        assert_eq!(split_fields(r"a\::b::c", "::"), ["a::b", "c"]);
+        // A backslash before anything else stays as it is
+        assert_eq!(split_fields(r"C:\dir,x", ","), [r"C:\dir", "x"]);
+    }
+
+    #[test]
+    fn split_json_escapes_fields() {
+        let fields: Vec<String> = split_fields("say \"hi\"|back\\\\slash|line\nbreak|tab\tbell\u{7}|ünï", "|");
+        assert_eq!(json_array(&fields), r#"["say \"hi\"","back\\slash","line\nbreak","tab\tbell\u0007","ünï"]"#);
+        assert_eq!(json_array(&split_fields("a,", ",")), r#"["a",""]"#);
+    }
+
+    #[test]
+    fn flattens_alpha_over_white() {
+        let img = image::DynamicImage::ImageLumaA8(image::GrayAlphaImage::from_raw(4, 1, vec![0, 255, 0, 0, 0, 128, 200, 255]).unwrap());
+        let flat: GrayImage = flatten_to_luma(img);
+        assert_eq!(flat.as_raw(), &[0, 255, 127, 200]);
+    }
+
+    // qr-gen --transparent writes the light pixels as transparent black, which a plain conversion to luma turns dark
+    #[test]
+    fn decodes_transparent_background() {
+        let bytes: &[u8] = include_bytes!("../tests/fixtures/transparent.png");
+        let img = image::load_from_memory(bytes).unwrap();
+        assert!(img.color().has_alpha());
+        assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(payloads(scan(&flatten_to_luma(img))), [b"Hello transparent"]);
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn decodes_avif() {
+        let img: GrayImage = fixture(include_bytes!("../tests/fixtures/hello.avif"), ImageFormat::Avif);
+        assert_eq!(payloads(scan(&img)), [b"Hello AVIF"]);
+    }
 }
@@ -0,0 +1,136 @@
+use image::{Rgba, RgbaImage};
+
+use crate::caption::Caption;
+use crate::shape::{in_rounded_rect, rounded_rect_path};
+use crate::{Cli, Color};
+
+// Text of the call-to-action ribbon.
+const LABEL: &str = "SCAN ME";
+
+// Width of the frame around the code and height of the ribbon, in modules.
+const THICKNESS: f64 = 1.0;
+const RIBBON: f64 = 4.0;
+
+
+// Frame templates for SVG and PNG output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameStyle {
+    // A plain rounded border
+    Simple,
+    // A border with the ribbon below the code
+    ScanMe,
+    // A more rounded border with the ribbon above the code
+    Badge,
+}
+
+impl std::str::FromStr for FrameStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "scanme" => Ok(FrameStyle::ScanMe),
+            "simple" => Ok(FrameStyle::Simple),
+            "badge" => Ok(FrameStyle::Badge),
+            _ => Err(format!("Unknown frame: {}. Use scanme, simple or badge", s)),
+        }
+    }
+}
+
+
+// A frame drawn around the code and its caption, the content. All geometry is in modules,
+// so the frame grows with --scale; coordinates are relative to the top-left corner of the content.
+pub struct Frame {
+    style: FrameStyle,
+    // Without a color the frame takes the dark color
+    pub color: Option<Color>,
+    label: Option<Caption>,
+}
+
+impl Frame {
+    // Returns the frame asked for with --frame, or none. A font that can't be loaded for the label is fatal.
+    pub fn from_args(args: &Cli) -> Option<Frame> {
+        let style: FrameStyle = args.frame?;
+        let label: Option<Caption> = (style != FrameStyle::Simple).then(|| {
+            Caption::new(LABEL, args.font.as_deref(), None).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            })
+        });
+        Some(Frame { style, color: args.frame_color, label })
+    }
+
+    // Returns how far the frame reaches beyond the content on the left, top, right and bottom.
+    pub fn margins(&self, scale: f64) -> (f64, f64, f64, f64) {
+        let (thickness, ribbon) = (THICKNESS * scale, RIBBON * scale);
+        match self.style {
+            FrameStyle::Simple => (thickness, thickness, thickness, thickness),
+            FrameStyle::ScanMe => (thickness, thickness, thickness, ribbon),
+            FrameStyle::Badge => (thickness, ribbon, thickness, thickness),
+        }
+    }
+
+    // Corner radius of the outside of the frame. It stays below the distance to the content's
+    // corners, which keep their square shape.
+    fn radius(&self, scale: f64) -> f64 {
+        match self.style {
+            FrameStyle::Simple | FrameStyle::ScanMe => scale,
+            FrameStyle::Badge => 2.5 * scale,
+        }
+    }
+
+    // Returns the left, top, width and height of the outside of the frame around content of the given size.
+    fn outline(&self, width: f64, height: f64, scale: f64) -> (f64, f64, f64, f64) {
+        let (left, top, right, bottom) = self.margins(scale);
+        (-left, -top, width + left + right, height + top + bottom)
+    }
+
+    // Returns the top of the ribbon and the label's left end and width, which keep a module of space on each side.
+    fn ribbon(&self, width: f64, height: f64, scale: f64) -> (f64, f64, f64) {
+        let top: f64 = match self.style {
+            FrameStyle::ScanMe => height,
+            _ => -RIBBON * scale,
+        };
+        (top, scale, width - scale * 2.0)
+    }
+
+    // Returns the top of the label's line, centered in the ribbon.
+    fn label_top(&self, label: &Caption, width: f64, height: f64, scale: f64) -> f64 {
+        let (top, _, label_width) = self.ribbon(width, height, scale);
+        top + (RIBBON * scale - label.line_height(label_width, scale)) / 2.0
+    }
+
+    // Returns the SVG elements of the frame around content of the given size: the border
+    // with a hole for the content, and the label in the text color.
+    pub fn svg(&self, width: f64, height: f64, scale: f64, fill: &str, text_fill: &str) -> String {
+        let (left, top, outer_width, outer_height) = self.outline(width, height, scale);
+        let mut result: String = format!("\t<path d=\"{}{}\" fill=\"{}\" fill-rule=\"evenodd\"/>\n",
+            rounded_rect_path(left, top, outer_width, outer_height, self.radius(scale)),
+            rounded_rect_path(0.0, 0.0, width, height, 0.0), fill);
+        if let Some(label) = &self.label {
+            let (_, label_left, label_width) = self.ribbon(width, height, scale);
+            let label_top: f64 = self.label_top(label, width, height, scale);
+            result += &format!("\t<path d=\"{}\" fill=\"{}\"/>\n", label.svg_path(label_left, label_top, label_width, scale), text_fill);
+        }
+        result
+    }
+
+    // Paints the frame into an image that holds content of the given size inset by the margins.
+    // Pixels are sampled at their center; the corners outside the frame stay as they are.
+    pub fn draw(&self, image: &mut RgbaImage, width: f64, height: f64, scale: f64, color: Rgba<u8>, text_color: Rgba<u8>) {
+        let (left, top, _, _) = self.margins(scale);
+        let (outer_left, outer_top, outer_width, outer_height) = self.outline(width, height, scale);
+        let radius: f64 = self.radius(scale);
+        for (px, py, pixel) in image.enumerate_pixels_mut() {
+            let (x, y) = (px as f64 + 0.5 - left, py as f64 + 0.5 - top);
+            let content: bool = (0.0..width).contains(&x) && (0.0..height).contains(&y);
+            if !content && in_rounded_rect(x - outer_left, y - outer_top, outer_width, outer_height, radius) {
+                *pixel = color;
+            }
+        }
+        if let Some(label) = &self.label {
+            let (_, label_left, label_width) = self.ribbon(width, height, scale);
+            let label_top: f64 = self.label_top(label, width, height, scale);
+            label.draw(image, left + label_left, top + label_top, label_width, scale, text_color);
+        }
+    }
+}
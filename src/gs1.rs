@@ -0,0 +1,281 @@
+use qrcodegen::{DataTooLong, Mask, QrCode, QrCodeEcc, QrSegment, QrSegmentMode, Version};
+
+use crate::segment::{self, Charset};
+use crate::spec;
+
+// The FNC1 character that ends a field of variable length, sent as the ASCII group separator.
+const GS: char = '\x1D';
+
+// Bits of the mode indicator that marks the data as GS1 element strings (FNC1 in first position).
+pub const FNC1_BITS: usize = 4;
+
+// Application identifiers by their first digits: the digits of the AI, the length of its data,
+// whether that length is fixed, and whether the data is all digits. From the GS1 General Specifications.
+const APPLICATION_IDENTIFIERS: [(&str, usize, usize, bool, bool); 62] = [
+    ("00", 2, 18, true, true),     // SSCC
+    ("01", 2, 14, true, true),     // GTIN
+    ("02", 2, 14, true, true),     // CONTENT
+    ("10", 2, 20, false, false),   // BATCH/LOT
+    ("11", 2, 6, true, true),      // PROD DATE
+    ("12", 2, 6, true, true),      // DUE DATE
+    ("13", 2, 6, true, true),      // PACK DATE
+    ("15", 2, 6, true, true),      // BEST BEFORE
+    ("16", 2, 6, true, true),      // SELL BY
+    ("17", 2, 6, true, true),      // USE BY
+    ("20", 2, 2, true, true),      // VARIANT
+    ("21", 2, 20, false, false),   // SERIAL
+    ("22", 2, 20, false, false),   // CPV
+    ("235", 3, 28, false, false),  // TPX
+    ("240", 3, 30, false, false),  // ADDITIONAL ID
+    ("241", 3, 30, false, false),  // CUST. PART No.
+    ("242", 3, 6, false, true),    // MTO VARIANT
+    ("243", 3, 20, false, false),  // PCN
+    ("250", 3, 30, false, false),  // SECONDARY SERIAL
+    ("251", 3, 30, false, false),  // REF. TO SOURCE
+    ("253", 3, 30, false, false),  // GDTI
+    ("254", 3, 20, false, false),  // GLN EXTENSION COMPONENT
+    ("255", 3, 25, false, true),   // GCN
+    ("30", 2, 8, false, true),     // VAR. COUNT
+    ("31", 4, 6, true, true),      // Trade measures with the decimal point position in the last digit
+    ("32", 4, 6, true, true),
+    ("33", 4, 6, true, true),      // Logistic measures
+    ("34", 4, 6, true, true),
+    ("35", 4, 6, true, true),
+    ("36", 4, 6, true, true),
+    ("37", 2, 8, false, true),     // COUNT
+    ("390", 4, 15, false, true),   // AMOUNT
+    ("391", 4, 18, false, true),
+    ("392", 4, 15, false, true),   // PRICE
+    ("393", 4, 18, false, true),
+    ("394", 4, 4, true, true),     // PRCNT OFF
+    ("400", 3, 30, false, false),  // ORDER NUMBER
+    ("401", 3, 30, false, false),  // GINC
+    ("402", 3, 17, true, true),    // GSIN
+    ("403", 3, 30, false, false),  // ROUTE
+    ("41", 3, 13, true, true),     // SHIP TO LOC, BILL TO and the other GLNs
+    ("420", 3, 20, false, false),  // SHIP TO POST
+    ("421", 3, 12, false, false),
+    ("422", 3, 3, true, true),     // ORIGIN
+    ("423", 3, 15, false, true),   // COUNTRY - INITIAL PROCESS.
+    ("424", 3, 3, true, true),     // COUNTRY - PROCESS.
+    ("425", 3, 15, false, true),   // COUNTRY - DISASSEMBLY
+    ("426", 3, 3, true, true),     // COUNTRY - FULL PROCESS
+    ("7001", 4, 13, true, true),   // NSN
+    ("7003", 4, 10, true, true),   // EXPIRY TIME
+    ("7006", 4, 6, true, true),    // FIRST FREEZE DATE
+    ("8003", 4, 30, false, false), // GRAI
+    ("8004", 4, 30, false, false), // GIAI
+    ("8005", 4, 6, true, true),    // PRICE PER UNIT
+    ("8006", 4, 18, true, true),   // ITIP
+    ("8007", 4, 34, false, false), // IBAN
+    ("8008", 4, 12, false, true),  // PROD TIME
+    ("8017", 4, 18, true, true),   // GSRN - PROVIDER
+    ("8018", 4, 18, true, true),   // GSRN - RECIPIENT
+    ("8020", 4, 25, false, false), // REF No.
+    ("90", 2, 30, false, false),   // INTERNAL
+    ("9", 2, 90, false, false),    // Company internal information, 91 to 99
+];
+
+// AIs starting with these digits have data of a predefined length, so no separator follows them.
+const PREDEFINED_LENGTH: [&str; 22] = [
+    "00", "01", "02", "03", "04", "11", "12", "13", "14", "15", "16", "17", "18", "19", "20", "31", "32", "33", "34", "35", "36", "41",
+];
+
+
+// Parses GS1 element strings written with the AIs in parentheses, e.g. (01)09501101530003(10)AB12,
+// checks each AI and its data, and returns them as encoded: without the parentheses and with
+// a separator after each field of variable length that isn't the last.
+pub fn element_string(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest: &str = text;
+    let mut separate: bool = false;
+    while !rest.is_empty() {
+        let Some((ai, after)) = rest.strip_prefix('(').and_then(|after| after.split_once(')')) else {
+            return Err(format!("Expected an application identifier in parentheses at '{}', e.g. (01)09501101530003.", rest));
+        };
+        let (value, next) = after.split_at(after.find('(').unwrap_or(after.len()));
+        check(ai, value)?;
+        if separate {
+            result.push(GS);
+        }
+        result += ai;
+        result += value;
+        separate = !PREDEFINED_LENGTH.contains(&&ai[..2]);
+        rest = next;
+    }
+    if result.is_empty() {
+        return Err("The GS1 data has no element strings.".to_string());
+    }
+    Ok(result)
+}
+
+
+// Checks that the AI is known and that its data has the right length and characters,
+// a valid check digit for the identification keys and a valid month and day for dates.
+fn check(ai: &str, value: &str) -> Result<(), String> {
+    let Some(&(_, _, length, fixed, numeric)) = APPLICATION_IDENTIFIERS.iter()
+        .find(|&&(prefix, digits, ..)| ai.len() == digits && ai.starts_with(prefix) && ai.bytes().all(|b| b.is_ascii_digit())) else {
+        return Err(format!("Unknown GS1 application identifier: ({}).", ai));
+    };
+    if fixed && value.len() != length {
+        return Err(format!("The data of AI ({}) must be {} characters long, not {}.", ai, length, value.len()));
+    }
+    if value.is_empty() || value.len() > length {
+        return Err(format!("The data of AI ({}) must be 1 to {} characters long, not {}.", ai, length, value.len()));
+    }
+    // Data is limited to the 82 characters of GS1 character set 82
+    if let Some(c) = value.chars().find(|&c| !(c.is_ascii_alphanumeric() || "!\"%&'()*+,-./:;<=>?_".contains(c))) {
+        return Err(format!("The data of AI ({}) has a character GS1 doesn't allow: {:?}.", ai, c));
+    }
+    if numeric && !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("The data of AI ({}) must be digits only: {}.", ai, value));
+    }
+
+    if matches!(ai, "00" | "01" | "02" | "8017" | "8018") || ai.starts_with("41") {
+        let expected: u32 = check_digit(&value[..value.len() - 1]);
+        if value[value.len() - 1..] != expected.to_string() {
+            return Err(format!("The check digit of AI ({}) should be {}: {}.", ai, expected, value));
+        }
+    }
+    if matches!(ai, "11" | "12" | "13" | "15" | "16" | "17" | "7006") {
+        // YYMMDD, where a day of 00 means the end of the month
+        let (month, day) = (value[2..4].parse::<u8>().unwrap_or(0), value[4..6].parse::<u8>().unwrap_or(0));
+        if !(1..=12).contains(&month) || day > 31 {
+            return Err(format!("The data of AI ({}) is not a date in the form YYMMDD: {}.", ai, value));
+        }
+    }
+    Ok(())
+}
+
+
+// Returns the GS1 check digit of the digits: weights of 3 and 1 from the right, up to the next multiple of 10.
+fn check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits.bytes().rev().enumerate()
+        .map(|(i, b)| (b - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+
+// Returns the segments for element strings at the given version like segment::make_segments.
+// After FNC1 in first position, alphanumeric mode reads % as the separator, so a literal % is doubled.
+pub fn make_segments(elements: &str, version: u8, optimize: bool) -> Vec<QrSegment> {
+    // Element strings are ASCII, so every segment counts its characters in bytes
+    let mut start: usize = 0;
+    segment::make_segments(elements, version, optimize, Charset::Utf8)
+        .into_iter()
+        .map(|seg| {
+            let run: &str = &elements[start..start + seg.num_chars()];
+            start += seg.num_chars();
+            match seg.mode() {
+                QrSegmentMode::Alphanumeric if run.contains('%') => QrSegment::make_alphanumeric(&run.replace('%', "%%")),
+                _ => seg,
+            }
+        })
+        .collect()
+}
+
+
+// Encodes the segments behind the FNC1 in first position mode indicator, which qrcodegen has no
+// segment for, at the smallest version between min and max that holds them. Like segment::encode,
+// the level is raised as far as the version still holds the data unless told not to.
+pub fn encode(segments: impl Fn(u8) -> Vec<QrSegment>, ecc: QrCodeEcc, min: Version, max: Version, mask: Option<Mask>, boost_ecc: bool)
+    -> Result<(QrCode, Vec<QrSegment>), DataTooLong> {
+    let mut error: Option<DataTooLong> = None;
+    for version in min.value()..=max.value() {
+        let segs: Vec<QrSegment> = segments(version);
+        if segs.iter().any(|seg| seg.num_chars() >= 1 << spec::char_count_bits(seg.mode(), version)) {
+            error = Some(DataTooLong::SegmentTooLong);
+            continue;
+        }
+        let bits: usize = FNC1_BITS + segs.iter().map(|seg| spec::segment_bits(seg, version)).sum::<usize>();
+        let capacity: usize = spec::num_data_codewords(version, ecc) * 8;
+        if bits > capacity {
+            error = Some(DataTooLong::DataOverCapacity(bits, capacity));
+            continue;
+        }
+
+        let mut ecc: QrCodeEcc = ecc;
+        for higher in [QrCodeEcc::Medium, QrCodeEcc::Quartile, QrCodeEcc::High] {
+            if boost_ecc && higher > ecc && bits <= spec::num_data_codewords(version, higher) * 8 {
+                ecc = higher;
+            }
+        }
+        let codewords: Vec<u8> = codewords(&segs, version, ecc);
+        return Ok((QrCode::encode_codewords(Version::new(version), ecc, &codewords, mask), segs));
+    }
+    Err(error.expect("the version range is not empty"))
+}
+
+
+// Returns the data codewords of a symbol: the FNC1 mode indicator, each segment with its mode
+// indicator and character count, the terminator and the pad bytes that fill the symbol's capacity.
+fn codewords(segs: &[QrSegment], version: u8, ecc: QrCodeEcc) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    spec::append_bits(&mut bits, 0b0101, FNC1_BITS);
+    for seg in segs {
+        spec::append_bits(&mut bits, mode_indicator(seg.mode()), 4);
+        spec::append_bits(&mut bits, seg.num_chars() as u32, spec::char_count_bits(seg.mode(), version));
+        bits.extend_from_slice(seg.data());
+    }
+
+    spec::pad_codewords(bits, 4, spec::num_data_codewords(version, ecc))
+}
+
+
+// Returns the 4-bit mode indicator of a segment mode, which qrcodegen keeps to itself.
+fn mode_indicator(mode: QrSegmentMode) -> u32 {
+    match mode {
+        QrSegmentMode::Numeric => 0b0001,
+        QrSegmentMode::Alphanumeric => 0b0010,
+        QrSegmentMode::Byte => 0b0100,
+        QrSegmentMode::Kanji => 0b1000,
+        QrSegmentMode::Eci => 0b0111,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gtin_check_digit() {
+        assert_eq!(check_digit("0950110153000"), 3);
+        assert_eq!(element_string("(01)09501101530003"), Ok("0109501101530003".to_string()));
+        let error: String = element_string("(01)09501101530004").unwrap_err();
+        assert!(error.contains("should be 3"), "{}", error);
+    }
+
+    // A separator ends a field of variable length before the next AI, but not the last field
+    #[test]
+    fn separator_after_variable_length_fields() {
+        assert_eq!(element_string("(10)AB12(17)271231"), Ok("10AB12\x1D17271231".to_string()));
+        assert_eq!(element_string("(10)AB12(21)X7"), Ok("10AB12\x1D21X7".to_string()));
+        assert_eq!(element_string("(17)271231(10)AB12"), Ok("1727123110AB12".to_string()));
+    }
+
+    #[test]
+    fn no_separator_after_fixed_length_fields() {
+        assert_eq!(element_string("(01)09501101530003(10)AB12"), Ok("010950110153000310AB12".to_string()));
+        assert_eq!(element_string("(01)09501101530003(17)271231"), Ok("010950110153000317271231".to_string()));
+    }
+
+    #[test]
+    fn dates_are_checked() {
+        assert!(element_string("(17)271331").unwrap_err().contains("YYMMDD"));
+        assert!(element_string("(17)270032").unwrap_err().contains("YYMMDD"));
+        // A day of 00 stands for the end of the month
+        assert!(element_string("(17)271200").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_element_strings() {
+        assert!(element_string("(99)").is_err());
+        assert!(element_string("(01)0950110153000").unwrap_err().contains("14 characters"));
+        assert!(element_string("(37)12AB").unwrap_err().contains("digits only"));
+        assert!(element_string("(999)X").unwrap_err().contains("Unknown"));
+        assert!(element_string("01)09501101530003").is_err());
+        assert!(element_string("").is_err());
+    }
+}
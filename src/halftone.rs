@@ -0,0 +1,69 @@
+use image::imageops::FilterType;
+use std::path::Path;
+
+use crate::Grid;
+
+// Sub-modules along each side of a module. The middle one keeps the module's color,
+// since that is where scanners sample; the ones around it show the picture.
+pub const SUBDIVISION: u32 = 3;
+
+
+// A picture blended into the data modules of a code, as in halftone QR codes: it is dithered
+// to dark and light at sub-module resolution over the symbol.
+pub struct Blend {
+    // Whether each sub-module of the picture is dark, row by row
+    dark: Vec<bool>,
+    // Sub-modules along each side of the symbol
+    side: u32,
+}
+
+impl Blend {
+    // Loads the picture and dithers it for the given code. It is cropped to a centered
+    // square and scaled to cover the symbol, so the quiet zone stays clear.
+    pub fn new(path: &Path, qr: &dyn Grid) -> Result<Blend, String> {
+        let side: u32 = qr.width() as u32 * SUBDIVISION;
+        let gray = image::open(path)
+            .map_err(|e| format!("Failed to open blend image '{}': {}", path.display(), e))?
+            .resize_to_fill(side, side, FilterType::Lanczos3)
+            .into_luma8();
+
+        // Floyd-Steinberg error diffusion keeps the mid tones as a mix of dark and light
+        let mut levels: Vec<f64> = gray.pixels().map(|p| p.0[0] as f64).collect();
+        let mut dark: Vec<bool> = vec![false; levels.len()];
+        for y in 0..side as usize {
+            for x in 0..side as usize {
+                let i: usize = y * side as usize + x;
+                dark[i] = levels[i] < 128.0;
+                let error: f64 = levels[i] - if dark[i] { 0.0 } else { 255.0 };
+                let mut spread = |dx: isize, dy: usize, weight: f64| {
+                    let nx: isize = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < side as usize && y + dy < side as usize {
+                        levels[(y + dy) * side as usize + nx as usize] += error * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+        Ok(Blend { dark, side })
+    }
+
+    // Returns whether the picture is dark at the pixel (px, py) of an image with a quiet zone
+    // of `border` modules and `scale` pixels per module, or none where the module keeps its
+    // own color: in the quiet zone, the function patterns and the middle of each module.
+    pub fn dark_at(&self, qr: &dyn Grid, border: i32, scale: u32, px: u32, py: u32) -> Option<bool> {
+        let x: i32 = (px / scale) as i32 - border;
+        let y: i32 = (py / scale) as i32 - border;
+        if !(0..qr.width()).contains(&x) || !(0..qr.height()).contains(&y) || qr.is_function_module(x, y) {
+            return None;
+        }
+        let (u, v) = ((px % scale) * SUBDIVISION / scale, (py % scale) * SUBDIVISION / scale);
+        if (u, v) == (SUBDIVISION / 2, SUBDIVISION / 2) {
+            return None;
+        }
+        let (sx, sy) = (x as u32 * SUBDIVISION + u, y as u32 * SUBDIVISION + v);
+        Some(self.dark[(sy * self.side + sx) as usize])
+    }
+}
@@ -0,0 +1,105 @@
+use qrcodegen::{QrCode, QrCodeEcc};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Cli, Glyphs, OutputType, SvgStyle, check_overwrite, parse_ecc_levels, segment, suffixed_path, to_svg_string, to_text_string, trace, write_symbol, QUIET_ZONE};
+
+
+// One line of input. Options that are left out fall back to the command line arguments.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    payload: String,
+    ecc: Option<String>,
+    output_type: Option<String>,
+    output_file: Option<PathBuf>,
+    border: Option<i32>,
+    scale: Option<i32>,
+}
+
+
+// One line of output, in the same order as the input lines.
+// PNG results name the written file; Text and SVG results carry the rendered data.
+#[derive(Serialize, Default)]
+struct Response {
+    line: usize,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+
+// Reads JSON requests from stdin, one per line, and answers each with a JSON result line.
+// Malformed lines and failed codes are reported in the result stream and processing goes on,
+// so other programs can drive qr-gen as a long-running subprocess.
+pub fn run(args: &Cli, output_file: &Path) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line: String = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let number: usize = index + 1;
+        let response: Response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle(&request, args, number, output_file).unwrap_or_else(|e| Response { error: Some(e), ..Default::default() }),
+            Err(e) => Response { error: Some(format!("Invalid request: {}", e)), ..Default::default() },
+        };
+
+        let response = Response { line: number, ..response };
+        writeln!(stdout, "{}", serde_json::to_string(&response).expect("responses always serialize"))?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+
+// Encodes and renders a single request.
+fn handle(request: &Request, args: &Cli, number: usize, output_file: &Path) -> Result<Response, String> {
+    trace::stage!("request", line = number);
+    let ecc: QrCodeEcc = match parse_ecc_levels(request.ecc.as_deref().unwrap_or(&args.ecc)).as_deref() {
+        Some([ecc]) => *ecc,
+        _ => return Err("Invalid error correction level. Use L, M, Q, or H.".to_string()),
+    };
+    let output_type: OutputType = match &request.output_type {
+        Some(name) => name.parse()?,
+        None => args.output_type.clone(),
+    };
+    let border: i32 = request.border.unwrap_or(args.border_width as i32);
+    let scale: i32 = request.scale.unwrap_or(args.scale as i32);
+    if border < 0 || scale < 1 {
+        return Err("Border must be non-negative and scale must be positive.".to_string());
+    }
+
+    let qr: QrCode = segment::encode_text(&request.payload, ecc, !args.no_optimize).map_err(|e| format!("Failed to generate QR code: {}", e))?;
+    let mut response = Response { ok: true, version: Some(qr.version().value()), ..Default::default() };
+
+    match output_type {
+        OutputType::TXT => response.data = Some(to_text_string(&qr, QUIET_ZONE, false, Glyphs::Block)),
+        OutputType::SVG => response.data = Some(to_svg_string(&qr, border as f64, scale as f64, &SvgStyle::default())),
+        OutputType::PNG => {
+            // Without an explicit file every line gets its own numbered file, e.g. qrcode-3.png
+            let path: PathBuf = match &request.output_file {
+                Some(path) => path.clone(),
+                None => suffixed_path(output_file, &number.to_string()),
+            };
+            check_overwrite(&path, args.force)?;
+            // Written like a single code, so the colors, --dpi, metadata and other PNG options apply too
+            let args = Cli { output_type: OutputType::PNG, output_file: Some(path.clone()), border_width: border as f64, scale: scale as f64, ..args.clone() };
+            write_symbol(&qr, &request.payload, request.payload.as_bytes(), &args, &path, false, &mut None).map_err(|e| e.to_string())?;
+            response.output = Some(path.display().to_string());
+        }
+        _ => return Err("Only Text, SVG and PNG output are supported in JSONL mode.".to_string()),
+    }
+
+    Ok(response)
+}
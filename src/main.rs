@@ -1,38 +1,361 @@
-use clap::Parser;
-use qrcodegen::{QrCode, QrCodeEcc};
-use image::{Luma, ImageBuffer, imageops::FilterType};
+mod build_info;
+mod caption;
+mod frame;
+mod gs1;
+mod halftone;
+mod jsonl;
+mod micro;
+mod pdf;
+mod penalty;
+mod rmqr;
+#[cfg(feature = "server")]
+mod server;
+mod shape;
+mod sheet;
+mod segment;
+mod spec;
+mod structured;
+mod terminal;
+mod trace;
+#[cfg(feature = "tui")]
+mod tui;
+
+use base64::Engine;
+use clap::{CommandFactory, FromArgMatches, parser::ValueSource};
+use qrcodegen::{DataTooLong, Mask, QrCode, QrCodeEcc, QrSegment, QrSegmentMode, Version};
+use image::{Luma, ImageBuffer, Rgba, RgbaImage, imageops::FilterType};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, Read, IsTerminal};
+use std::io::{self, Read, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use caption::Caption;
+use frame::{Frame, FrameStyle};
+use halftone::Blend;
+use segment::Charset;
+use shape::{EyeShape, ModuleStyle, Shape};
+use terminal::{GraphicsMode, Protocol};
+
+// Width of the light margin, in modules, that scanners need around a QR code.
+const QUIET_ZONE: i32 = 4;
 
-#[derive(Parser, Debug)]
+// PNG file written when no --output-file is given.
+const DEFAULT_OUTPUT_FILE: &str = "qrcode.png";
+
+#[derive(clap::Parser, Debug, Clone)]
 #[command(version, about = "Create a QR code from text file or piped data")]
 struct Cli {
-    #[arg(short, long, value_name = "ECC", help = "Error correction level. Use L, M, Q, or H.", default_value = "M")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, value_name = "ECC", help = "Error correction level. Use L, M, Q, or H, a list like L,H, or all. Listed levels are kept as given, as with --no-boost-ecc.", default_value = "M")]
     ecc: String,
 
+    #[arg(long, value_name = "VERSION", help = "Smallest version (1-40) to encode at, e.g. to keep every code the same size.", value_parser = clap::value_parser!(u8).range(1..=40))]
+    min_version: Option<u8>,
+
+    #[arg(long, value_name = "VERSION", help = "Largest version (1-40) to encode at. Data that doesn't fit is an error.", value_parser = clap::value_parser!(u8).range(1..=40))]
+    max_version: Option<u8>,
+
+    #[arg(long, value_name = "MASK", help = "Mask pattern (0-7) to use instead of the one with the lowest penalty score.", value_parser = clap::value_parser!(u8).range(0..=7))]
+    mask: Option<u8>,
+
+    #[arg(long, help = "Keep the error correction level as given, instead of raising it when that fits in the same version.")]
+    no_boost_ecc: bool,
+
+    #[arg(long, help = "Encode the text in a single mode instead of mixing numeric, alphanumeric and byte segments for the smallest code.")]
+    no_optimize: bool,
+
+    #[arg(long, conflicts_with_all = ["charset", "append", "null_separated", "jsonl"], help = "Split data that doesn't fit in one code over up to 16 linked codes, e.g. qrcode-1.png, qrcode-2.png. --max-version caps the size of each.")]
+    structured_append: bool,
+
+    #[arg(long, conflicts_with_all = ["file", "binary", "input_encoding", "charset", "append", "structured_append", "jsonl"], help = "Encode GS1 element strings for retail and healthcare scanners, with the AIs in parentheses, e.g. (01)09501101530003(17)271231(10)AB12. Each AI and its data are checked.")]
+    gs1: bool,
+
+    #[arg(long, value_name = "SYMBOLOGY", default_value = "qr", help = "Kind of code: qr, microqr for the small Micro QR codes (M1-M4) that fit a few characters, or rmqr for rectangular codes 7 to 17 modules tall, in Text, SVG, PNG or PGM output and without logos or pictures.")]
+    symbology: Symbology,
+
+    #[arg(long, value_name = "MODULES", help = "Height of rMQR codes: 7, 9, 11, 13, 15 or 17 modules. By default the smallest code that holds the data.")]
+    rmqr_height: Option<u8>,
+
     #[arg(short, long, value_name = "INPUT", help = "Unicode text file or piped data.")]
     input: Option<PathBuf>,
 
-    #[arg(short = 't', long, value_name = "OUTPUT_TYPE", help = "Output file/data types. Use Text, SVG or PNG", default_value = "Text")]
+    #[arg(long, help = "Read input from stdin, even if it is a terminal.")]
+    stdin: bool,
+
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "stdin", "null_separated", "jsonl"], help = "Encode the bytes of a small file as they are, in byte mode.")]
+    file: Option<PathBuf>,
+
+    #[arg(long, conflicts_with_all = ["file", "null_separated", "jsonl", "charset"], help = "Encode the input bytes as they are, in byte mode, instead of as UTF-8 text.")]
+    binary: bool,
+
+    #[arg(long, value_name = "ENCODING", conflicts_with_all = ["file", "binary", "jsonl", "charset"], help = "Decode the input from hex or base64 and encode the bytes in byte mode.")]
+    input_encoding: Option<InputEncoding>,
+
+    #[arg(long, value_name = "CHARSET", conflicts_with = "file", help = "Character set for the text, announced to scanners with an ECI segment. Use latin1, shift_jis or utf8.")]
+    charset: Option<Charset>,
+
+    #[arg(long, value_name = "TEXT", help = "Text appended to the input as its own segment, e.g. a tracking tag.")]
+    append: Option<String>,
+
+    #[arg(short = '0', long, help = "Split the input on NUL bytes and create one QR code per record.")]
+    null_separated: bool,
+
+    #[arg(long, conflicts_with = "null_separated", help = "Read one JSON request per line from stdin and print one JSON result per line.")]
+    jsonl: bool,
+
+    #[arg(short = 't', long, value_name = "OUTPUT_TYPE", help = "Output file/data types. Use Auto, Text, SVG, PNG, JPEG, WebP, HTML, DataURI, DataURI-SVG, Matrix, Matrix-Text, Sixel, Kitty, iTerm2, RAW1, PBM, PGM, XBM, C, PDF, EPS or DXF. Auto picks the type from the --output-file extension and is used when only -o is given.", default_value = "Text")]
     output_type: OutputType,
 
-    #[arg(short = 'o', long, value_name = "OUTPUT_FILE", help = "Output file path only used for PNG.", default_value = "qrcode.png")]
-    output_file: PathBuf,
+    #[arg(long, value_name = "GLYPHS", help = "Characters for Text output. Use block, ascii, half, quadrant or braille.", default_value = "block")]
+    glyphs: Glyphs,
+
+    #[arg(long, value_name = "GRID", help = "Modules per character for the braille glyphs. 2x4 uses all eight dots and is the densest; 2x3 uses the six-dot subset, which renders more squarely in some fonts but takes a third more lines.", default_value = "2x4")]
+    braille_grid: BrailleGrid,
+
+    #[arg(long, conflicts_with = "glyphs", help = "Pack two rows of modules into each line of Text output with half blocks. Same as --glyphs half.")]
+    compact: bool,
+
+    #[arg(long, help = "Draw Text output with ANSI background colors, so it keeps its contrast on any terminal theme. Use with the block or half glyphs.")]
+    ansi: bool,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the dark modules as hex, e.g. #1a73e8, for PNG, SVG and ANSI output. SVG output also takes currentColor. ANSI output then uses 24-bit color.")]
+    fg: Option<Paint>,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the light modules and border as hex, e.g. #fff8e7, for PNG, SVG and ANSI output. SVG output also takes currentColor or none. ANSI output then uses 24-bit color.")]
+    bg: Option<Paint>,
+
+    #[arg(long, help = "Swap the dark and light colors, for light modules on a dark background. Some scanners reject such codes.")]
+    invert: bool,
+
+    #[arg(long, help = "Keep colors whose contrast is too low for scanners with a warning instead of refusing them. Has no effect under --strict.")]
+    force_colors: bool,
+
+    #[arg(short = 'o', long, value_name = "OUTPUT_FILE", help = "Output file path. PNG defaults to qrcode.png; other types go to stdout unless a file is given. Use - to send any type to stdout.")]
+    output_file: Option<PathBuf>,
+
+    #[arg(long, value_name = "TAR", conflicts_with_all = ["jsonl", "bg_image"], help = "Write the outputs as entries of a tar archive instead of separate files. Use - for stdout.")]
+    tar: Option<PathBuf>,
+
+    #[arg(short = 'd', long, value_name = "OUTPUT_DIR", help = "Directory for the output files. Created if missing.")]
+    output_dir: Option<PathBuf>,
+
+    #[arg(short = 'b', long, value_name = "BORDER_WIDTH", help = "SVG or PNG border surrounding the QR code. Fractions are allowed for SVG.", default_value_t = 4.0)]
+    border_width: f64,
+
+    #[arg(short = 's', long, value_name = "SCALE", help = "Scale of the SVG or PNG image. Fractions are allowed for SVG.", default_value_t = 10.0)]
+    scale: f64,
+
+    #[arg(long, value_name = "PIXELS", conflicts_with_all = ["scale", "physical", "module_size"], help = "Edge length of the code with its border in pixels, instead of --scale. Raster output uses the largest whole scale that fits and PNG pads the rest into the border; SVG scales exactly.")]
+    size: Option<u32>,
+
+    #[arg(long, value_name = "RADIUS", help = "Corner radius of the SVG background, in modules.", default_value_t = 0.0)]
+    bg_radius: f64,
+
+    #[arg(long, value_name = "BLEED", help = "Extend the SVG background this many modules past the image edge for full-bleed printing.", default_value_t = 0.0)]
+    bg_bleed: f64,
+
+    #[arg(long, value_name = "SHAPE", help = "Shape of the dark modules in SVG and PNG output. Use square, rounded, dots, diamond, squircle, vertical-bars or horizontal-bars. The finder, timing and alignment patterns stay square for every shape.", default_value = "square")]
+    shape: Shape,
+
+    #[arg(long, value_name = "RATIO", help = "Corner radius of rounded modules as a fraction of the module size, up to 0.5.", default_value_t = 0.5)]
+    corner_radius: f64,
+
+    #[arg(long, value_name = "RATIO", help = "Radius of dots as a fraction of the module size, up to 0.5.", default_value_t = 0.45)]
+    dot_radius: f64,
+
+    #[arg(long, value_name = "SHAPE", help = "Shape of the three finder patterns in SVG and PNG output. Use square, rounded or circle.")]
+    eye_shape: Option<EyeShape>,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the three finder patterns as hex, for SVG and PNG output.")]
+    eye_color: Option<Color>,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the top-left finder pattern as hex, in place of --eye-color.")]
+    eye_color_tl: Option<Color>,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the top-right finder pattern as hex, in place of --eye-color.")]
+    eye_color_tr: Option<Color>,
+
+    #[arg(long, value_name = "COLOR", help = "Color of the bottom-left finder pattern as hex, in place of --eye-color.")]
+    eye_color_bl: Option<Color>,
+
+    #[arg(long, value_name = "TEXT", help = "Text to print centered under the code in PNG, SVG and PDF output.")]
+    caption: Option<String>,
+
+    #[arg(long, value_name = "FONT", help = "TrueType or OpenType font file for the caption and frame label. Defaults to DejaVu Sans, Liberation Sans or Arial if installed.")]
+    font: Option<PathBuf>,
+
+    #[arg(long, value_name = "SIZE", requires = "caption", help = "Font size of the caption in pixels, or points for PDF. Defaults to two modules; long captions shrink to the code's width.")]
+    font_size: Option<f64>,
+
+    #[arg(long, value_name = "FRAME", conflicts_with_all = ["bg_image", "bg_bleed"], help = "Frame around PNG and SVG output: scanme and badge add a \"SCAN ME\" ribbon below or above the code, simple is a plain border.")]
+    frame: Option<FrameStyle>,
+
+    #[arg(long, value_name = "COLOR", requires = "frame", help = "Color of the frame as hex. Defaults to the --fg color.")]
+    frame_color: Option<Color>,
+
+    #[arg(long, conflicts_with_all = ["caption", "frame", "bg_image", "bg_bleed", "null_separated", "jsonl", "structured_append"], help = "Make a card to share a URL, as PNG or SVG: the code at ECC H unless --ecc is given, in a simple rounded frame, with the URL as the caption.")]
+    url_card: bool,
+
+    #[arg(long, value_name = "QUALITY", help = "JPEG quality from 1 to 100.", value_parser = clap::value_parser!(u8).range(1..=100), default_value_t = 90)]
+    quality: u8,
+
+    #[arg(long, help = "Emit only the HTML table, without the surrounding document, e.g. for email templates.")]
+    html_fragment: bool,
+
+    #[arg(long, help = "Record the payload, error correction level, version and creation time in PNG text chunks.")]
+    embed_metadata: bool,
+
+    #[arg(long, value_name = "LANGUAGE", conflicts_with = "output_type", help = "Print the module matrix as source code instead of an image. Use rust or c.")]
+    codegen: Option<Language>,
+
+    #[arg(long, value_name = "MM", help = "Edge length of one module in millimeters for DXF output.", default_value_t = 1.0)]
+    module_size_mm: f64,
+
+    #[arg(long, value_name = "WHEN", help = "Whether Sixel, Kitty and iTerm2 output draw an image or falls back to Text. Use auto, always or never. Auto checks TERM and TERM_PROGRAM.", default_value = "auto")]
+    graphics: GraphicsMode,
+
+    #[arg(long, value_name = "PAGE", help = "Page size for PDF output. Use A4 or Letter. Defaults to a page that fits the code.")]
+    page: Option<PageSize>,
+
+    #[arg(long, value_name = "MARGIN", value_parser = parse_physical_size, help = "Blank margin around the code on PDF pages, e.g. 5mm.")]
+    margin: Option<f64>,
+
+    #[arg(long, visible_alias = "width", value_name = "SIZE", value_parser = parse_physical_size, help = "Printed size of the code including border, e.g. 30mm, 3cm or 1.2in. Overrides --scale. SVG output gets it as its width and height; raster output needs --dpi.")]
+    physical: Option<f64>,
+
+    #[arg(long, value_name = "SIZE", value_parser = parse_physical_size, conflicts_with_all = ["physical", "scale"], help = "Printed size of one module, e.g. 0.5mm. Works like --physical for the whole code and also sets the module size of DXF output.")]
+    module_size: Option<f64>,
+
+    #[arg(long, value_name = "DPI", help = "Print resolution in dots per inch. Written to PNG files so print software sizes them right, and used with --physical and --module-size.")]
+    dpi: Option<f64>,
+
+    #[arg(long, value_name = "BG_IMAGE", conflicts_with = "caption", help = "Background image to place the PNG QR code on, centered. The code keeps its shapes and colors; the background shows through its quiet zone.")]
+    bg_image: Option<PathBuf>,
+
+    #[arg(long, conflicts_with_all = ["bg", "bg_image"], help = "Leave the light modules and border transparent, for PNG and SVG output.")]
+    transparent: bool,
+
+    #[arg(long, value_name = "GRADIENT", help = "Fill the dark modules of PNG and SVG output with a gradient: linear:#ff0000:#0000ff[:angle] or radial:#ff0000:#0000ff. The angle is in degrees clockwise from left to right. Finder patterns keep the --fg color.")]
+    gradient: Option<Gradient>,
 
-    #[arg(short = 'b', long, value_name = "BORDER_WIDTH", help = "SVG or PNG border surrounding the QR code.", default_value_t = 4)]
-    border_width: i32,
+    #[arg(long, value_name = "LOGO", conflicts_with = "bg_image", help = "Image to place in the center of PNG and SVG output. Raises the error correction level to H unless --ecc is given.")]
+    logo: Option<PathBuf>,
 
-    #[arg(short = 's', long, value_name = "SCALE", help = "Scale of the SVG or PNG image.", default_value_t = 10)]
-    scale: i32,
+    #[arg(long, requires = "logo", help = "Link to the logo file from SVG output instead of embedding it.")]
+    logo_link: bool,
+
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "20%", help = "Width of the logo as a share of the code's width, e.g. 20%.")]
+    logo_size: f64,
+
+    #[arg(long, value_name = "IMAGE", conflicts_with = "bg_image", help = "Picture to blend into the data modules of PNG output as a halftone. The middle of each module keeps its color so the code still scans. Needs a scale of at least 3 and raises the error correction level to H unless --ecc is given.")]
+    blend_image: Option<PathBuf>,
+
+    #[arg(long, help = "Print codeword statistics of the generated QR code to stderr.")]
+    stats: bool,
+
+    #[arg(long, help = "Print the penalty score of every mask pattern to stderr.")]
+    mask_penalty: bool,
+
+    #[arg(long, help = "Print the module grid to stderr, labeled by function pattern, for debugging.")]
+    dump_matrix: bool,
+
+    #[arg(short = 'f', long, help = "Overwrite the output file if it already exists.")]
+    force: bool,
+
+    #[arg(long, value_name = "STRATEGY", help = "What batch outputs do when a file already exists. Use skip, overwrite or suffix.", default_value = "suffix")]
+    on_collision: Collision,
+
+    #[arg(short = 'v', long, help = "Print additional details to stderr.")]
+    verbose: bool,
+
+    #[arg(short = 'q', long, help = "Leave out advisory warnings, such as the one that some scanners reject inverted codes.")]
+    quiet: bool,
+
+    #[arg(long, help = "Abort instead of warning when the quiet zone around the code is not clear or a logo hides more than error correction restores, and refuse colors with too little contrast even with --force-colors.")]
+    strict: bool,
+
+    #[arg(long, help = "Print the version, git commit, compiler, target and enabled features, then exit.")]
+    build_info: bool,
+
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with_all = ["null_separated", "structured_append", "gs1"], help = "Preview the code in an interactive terminal UI and save it as PNG from there.")]
+    tui: bool,
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+    #[command(about = "Print the maximum payload size for each error correction level and version")]
+    Capacities {
+        #[arg(long, value_name = "VERSION", help = "Only show this version (1-40).", value_parser = clap::value_parser!(u8).range(1..=40))]
+        version: Option<u8>,
+
+        #[arg(long, value_name = "MODE", help = "Only show one encoding mode: numeric, alphanumeric, byte or kanji.", value_parser = spec::parse_mode)]
+        mode: Option<QrSegmentMode>,
+    },
+
+    #[command(about = "Lay out many codes on printable pages, optionally with cut marks")]
+    Sheet(sheet::SheetArgs),
+
+    #[cfg(feature = "server")]
+    #[command(about = "Serve QR codes over HTTP: GET /qr?data=...&ecc=M&type=png|svg&border=4&scale=10")]
+    Serve {
+        #[arg(long, value_name = "ADDR", help = "Address to listen on.", default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+}
+
+
+// Kinds of two-dimensional code that can be generated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symbology {
+    Qr,
+    MicroQr,
+    Rmqr,
+}
+
+impl std::str::FromStr for Symbology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "qr" => Ok(Symbology::Qr),
+            "microqr" | "micro" => Ok(Symbology::MicroQr),
+            "rmqr" => Ok(Symbology::Rmqr),
+            _ => Err(format!("Unknown symbology: {}. Use qr, microqr or rmqr", s)),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 enum OutputType {
+    // Resolved from the output file extension in main before anything is rendered
+    Auto,
     TXT,
     SVG,
     PNG,
+    JPEG,
+    WEBP,
+    HTML,
+    DATAURI,
+    SVGDATAURI,
+    MATRIX,
+    MATRIXTEXT,
+    SIXEL,
+    KITTY,
+    ITERM2,
+    RAW1,
+    PBM,
+    PGM,
+    XBM,
+    C,
+    PDF,
+    EPS,
+    DXF,
 }
 
 impl std::str::FromStr for OutputType {
@@ -40,151 +363,2777 @@ impl std::str::FromStr for OutputType {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
+            "auto" => Ok(OutputType::Auto),
             "text" => Ok(OutputType::TXT),
             "svg" => Ok(OutputType::SVG),
             "png" => Ok(OutputType::PNG),
-            _ => Err(format!("Unknown output type: {}. Use Text, SVG or PNG", s)),
+            "jpeg" | "jpg" => Ok(OutputType::JPEG),
+            "webp" => Ok(OutputType::WEBP),
+            "html" => Ok(OutputType::HTML),
+            "datauri" | "datauri-png" => Ok(OutputType::DATAURI),
+            "datauri-svg" => Ok(OutputType::SVGDATAURI),
+            "matrix" => Ok(OutputType::MATRIX),
+            "matrix-text" => Ok(OutputType::MATRIXTEXT),
+            "sixel" => Ok(OutputType::SIXEL),
+            "kitty" => Ok(OutputType::KITTY),
+            "iterm2" | "iterm" => Ok(OutputType::ITERM2),
+            "raw1" => Ok(OutputType::RAW1),
+            "pbm" => Ok(OutputType::PBM),
+            "pgm" => Ok(OutputType::PGM),
+            "xbm" => Ok(OutputType::XBM),
+            "c" => Ok(OutputType::C),
+            "pdf" => Ok(OutputType::PDF),
+            "eps" => Ok(OutputType::EPS),
+            "dxf" => Ok(OutputType::DXF),
+            _ => Err(format!("Unknown output type: {}. Use Auto, Text, SVG, PNG, JPEG, WebP, HTML, DataURI, DataURI-SVG, Matrix, Matrix-Text, Sixel, Kitty, iTerm2, RAW1, PBM, PGM, XBM, C, PDF, EPS or DXF", s)),
         }
     }
 }
 
 
-fn main() -> io::Result<()> {
-    let args = Cli::parse();
-
-    let ecc: QrCodeEcc = match args.ecc.to_lowercase().as_str() {
-        "l" => QrCodeEcc::Low,
-        "m" => QrCodeEcc::Medium,
-        "q" => QrCodeEcc::Quartile,
-        "h" => QrCodeEcc::High,
-        _ => {
-            eprintln!("Invalid error correction level. Use L, M, Q, or H.");
-            return Ok(());
+impl OutputType {
+    // File extension for outputs of this type.
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputType::Auto => unreachable!("the output type is resolved before use"),
+            OutputType::TXT => "txt",
+            OutputType::SVG => "svg",
+            OutputType::PNG => "png",
+            OutputType::JPEG => "jpg",
+            OutputType::WEBP => "webp",
+            OutputType::HTML => "html",
+            OutputType::DATAURI | OutputType::SVGDATAURI | OutputType::MATRIXTEXT => "txt",
+            OutputType::MATRIX => "json",
+            OutputType::SIXEL => "six",
+            OutputType::KITTY => "kitty",
+            OutputType::ITERM2 => "iterm2",
+            OutputType::RAW1 => "raw",
+            OutputType::PBM => "pbm",
+            OutputType::PGM => "pgm",
+            OutputType::XBM => "xbm",
+            OutputType::C => "h",
+            OutputType::PDF => "pdf",
+            OutputType::EPS => "eps",
+            OutputType::DXF => "dxf",
         }
-    };
+    }
+
+    // Terminal image protocol of the types drawn inline in the terminal.
+    fn protocol(&self) -> Option<Protocol> {
+        match self {
+            OutputType::SIXEL => Some(Protocol::Sixel),
+            OutputType::KITTY => Some(Protocol::Kitty),
+            OutputType::ITERM2 => Some(Protocol::ITerm2),
+            _ => None,
+        }
+    }
 
-    // Call the read_input function
-    let text: String = read_input(&args.input)?;
+    // Binary image formats go to a file even without -o, and only to stdout with -o -.
+    fn needs_file(&self) -> bool {
+        matches!(self, OutputType::PNG | OutputType::JPEG | OutputType::WEBP | OutputType::PDF)
+    }
 
-    // Attempt to encode the text into a QR code
-    match QrCode::encode_text(&text, ecc) {
-        Ok(qr) => {
-            match args.output_type {
-                OutputType::TXT => print_qr(&qr),
-                OutputType::SVG => println!("{}", to_svg_string(&qr, args.border_width, args.scale)),
-                OutputType::PNG => {
-                    if let Err(e) = write_to_png_scaled(&qr, args.border_width, args.scale as u32, &args.output_file) {
-                        eprintln!("Error writing PNG: {}", e);
-                    }
+    // Returns the output type matching a file extension, if it is one of ours.
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "txt" => Some(OutputType::TXT),
+            "svg" => Some(OutputType::SVG),
+            "png" => Some(OutputType::PNG),
+            "jpg" | "jpeg" => Some(OutputType::JPEG),
+            "webp" => Some(OutputType::WEBP),
+            "html" | "htm" => Some(OutputType::HTML),
+            "json" => Some(OutputType::MATRIX),
+            "six" | "sixel" => Some(OutputType::SIXEL),
+            "raw" => Some(OutputType::RAW1),
+            "pbm" => Some(OutputType::PBM),
+            "pgm" => Some(OutputType::PGM),
+            "xbm" => Some(OutputType::XBM),
+            "h" | "c" => Some(OutputType::C),
+            "pdf" => Some(OutputType::PDF),
+            "eps" => Some(OutputType::EPS),
+            "dxf" => Some(OutputType::DXF),
+            _ => None,
+        }
+    }
+}
+
+
+// Paper sizes for PDF pages and sheets.
+#[derive(Debug, Clone, Copy)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl std::str::FromStr for PageSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a4" => Ok(PageSize::A4),
+            "letter" => Ok(PageSize::Letter),
+            _ => Err(format!("Unknown page size: {}. Use A4 or Letter", s)),
+        }
+    }
+}
+
+impl PageSize {
+    // Width and height of the page in inches, portrait.
+    pub fn inches(self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (210.0 / 25.4, 297.0 / 25.4),
+            PageSize::Letter => (8.5, 11.0),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+enum Collision {
+    Skip,
+    Overwrite,
+    Suffix,
+}
+
+impl std::str::FromStr for Collision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(Collision::Skip),
+            "overwrite" => Ok(Collision::Overwrite),
+            "suffix" => Ok(Collision::Suffix),
+            _ => Err(format!("Unknown collision strategy: {}. Use skip, overwrite or suffix", s)),
+        }
+    }
+}
+
+
+// Text encodings of binary input, for passing it through text-only pipelines.
+#[derive(Debug, Clone, Copy)]
+enum InputEncoding {
+    Hex,
+    Base64,
+}
+
+impl std::str::FromStr for InputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(InputEncoding::Hex),
+            "base64" => Ok(InputEncoding::Base64),
+            _ => Err(format!("Unknown input encoding: {}. Use hex or base64", s)),
+        }
+    }
+}
+
+impl InputEncoding {
+    // Returns the bytes the text stands for. Whitespace is ignored, so wrapped lines and a trailing newline are fine.
+    fn decode(self, text: &[u8]) -> Result<Vec<u8>, String> {
+        let digits: Vec<u8> = text.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+        match self {
+            InputEncoding::Hex => {
+                if !digits.len().is_multiple_of(2) {
+                    return Err("Input is not valid hex: it has an odd number of digits.".to_string());
                 }
+                digits.chunks(2)
+                    .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                        .ok_or_else(|| format!("Input is not valid hex: '{}'.", String::from_utf8_lossy(pair))))
+                    .collect()
             }
+            InputEncoding::Base64 => base64::engine::general_purpose::STANDARD.decode(&digits)
+                .map_err(|e| format!("Input is not valid base64: {}.", e)),
         }
-        Err(e) => {
-            eprintln!("Failed to generate QR code: {}", e);
+    }
+}
+
+
+// Character sets for Text output. Denser sets pack several modules into one character.
+#[derive(Debug, Clone, Copy)]
+enum Glyphs {
+    Block,
+    Ascii,
+    Half,
+    Quadrant,
+    Braille,
+    // Braille with the six-dot subset, 2x3 modules per character
+    SixDotBraille,
+}
+
+impl std::str::FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(Glyphs::Block),
+            "ascii" => Ok(Glyphs::Ascii),
+            "half" => Ok(Glyphs::Half),
+            "quadrant" => Ok(Glyphs::Quadrant),
+            "braille" => Ok(Glyphs::Braille),
+            _ => Err(format!("Unknown glyph set: {}. Use block, ascii, half, quadrant or braille", s)),
         }
     }
+}
 
-    Ok(())
+impl Glyphs {
+    // Width and height, in modules, of the area one character covers.
+    fn cell_size(self) -> (i32, i32) {
+        match self {
+            Glyphs::Block | Glyphs::Ascii => (1, 1),
+            Glyphs::Half => (1, 2),
+            Glyphs::Quadrant => (2, 2),
+            Glyphs::Braille => (2, 4),
+            Glyphs::SixDotBraille => (2, 3),
+        }
+    }
+
+    // Appends the text for one cell. Bit i of `dark` is set when the module at
+    // row-major position i of the cell is dark.
+    fn push_cell(self, result: &mut String, dark: usize) {
+        const HALF: [char; 4] = [' ', '▀', '▄', '█'];
+        const QUADRANT: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+        // Braille dots are numbered down the left column first, then the right, then the bottom row
+        const BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+        match self {
+            Glyphs::Block => result.push_str(if dark != 0 { "██" } else { "  " }),
+            Glyphs::Ascii => result.push_str(if dark != 0 { "##" } else { "  " }),
+            Glyphs::Half => result.push(HALF[dark]),
+            Glyphs::Quadrant => result.push(QUADRANT[dark]),
+            Glyphs::Braille => {
+                let dots: u32 = (0..8).filter(|i| dark & (1 << i) != 0).map(|i| BRAILLE_DOTS[i]).sum();
+                result.push(char::from_u32(0x2800 + dots).unwrap_or(' '));
+            }
+            Glyphs::SixDotBraille => {
+                let dots: u32 = (0..6).filter(|i| dark & (1 << i) != 0).map(|i| BRAILLE_DOTS[i]).sum();
+                result.push(char::from_u32(0x2800 + dots).unwrap_or(' '));
+            }
+        }
+    }
 }
 
 
-/*---- Utilities ----*/
+// Modules per character for the braille glyphs: all eight dots, or the six-dot subset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BrailleGrid {
+    TwoByFour,
+    TwoByThree,
+}
 
+impl std::str::FromStr for BrailleGrid {
+    type Err = String;
 
-// Check if there's data in the standard input
-// Otherwise, read from a file
-fn read_input(input: &Option<PathBuf>) -> Result<String, io::Error> {
-    let mut text = String::new();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "2x4" => Ok(BrailleGrid::TwoByFour),
+            "2x3" => Ok(BrailleGrid::TwoByThree),
+            _ => Err(format!("Unknown braille grid: {}. Use 2x4 or 2x3", s)),
+        }
+    }
+}
 
-    if let Some(file_path) = input {
-        File::open(file_path)
-            .and_then(|mut file| file.read_to_string(&mut text))
-            .map_err(|e| {
-                eprintln!("Error reading file '{}': {}", file_path.display(), e);
-                e
-            })?;
-    } else if !io::stdin().is_terminal() {
-        io::stdin().read_to_string(&mut text)?;
-    } else {
-        eprintln!("No input provided. Please specify a file or pipe data.");
-        std::process::exit(1); // Exit the program with a non-zero status
+
+// An RGB color given in hex, as #rrggbb or the short #rgb form.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl Color {
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+    const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+
+    // Relative luminance as defined by WCAG, from 0 for black to 1 for white.
+    fn luminance(self) -> f64 {
+        let linear = |channel: u8| {
+            let c: f64 = channel as f64 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
     }
 
-    Ok(text)
+    // WCAG contrast ratio with another color, from 1 for the same luminance to 21 for black on white.
+    fn contrast(self, other: Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
 }
 
 
-// Returns a string of SVG code for an image depicting
-// the given QR Code, with the given number of border modules.
-// The string always uses Unix newlines (\n), regardless of the platform.
-fn to_svg_string(qr: &QrCode, border: i32, scale: i32) -> String {
-    assert!(border >= 0, "Border must be non-negative");
-    assert!(scale > 0, "Scale must be positive");
-    let mut result = String::new();
-    result += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
-    result += "<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">\n";
-    let dimension = qr.size().checked_add(border.checked_mul(2).unwrap()).unwrap() * scale;
-    result += &format!(
-        "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" viewBox=\"0 0 {0} {0}\" width=\"{0}\" height=\"{0}\" stroke=\"none\">\n", dimension);
-    result += "\t<rect width=\"100%\" height=\"100%\" fill=\"#FFFFFF\"/>\n";
-    result += "\t<path d=\"";
-    for y in 0..qr.size() {
-        for x in 0..qr.size() {
-            if qr.get_module(x, y) {
-                if x != 0 || y != 0 {
-                    result += " ";
-                }
-                result += &format!("M{},{}h{}v{}h-{}z", (x + border) * scale, (y + border) * scale, scale, scale, scale);
-            }
+// Lowest contrast ratio between the dark and light colors that scanners reliably tell apart.
+// The 3:1 WCAG asks of graphics is too little for cheap cameras in poor light.
+const MIN_CONTRAST: f64 = 4.5;
+
+
+// Returns whether colors below the minimum contrast are refused. --force-colors keeps them
+// with a warning, except under --strict, which no option overrides.
+fn refuses_contrast(force_colors: bool, strict: bool) -> bool {
+    strict || !force_colors
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: &str = s.strip_prefix('#').unwrap_or(s);
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+        let channels: Option<(u8, u8, u8)> = match hex.len() {
+            _ if !hex.is_ascii() => None,
+            6 => channel(&hex[0..2]).zip(channel(&hex[2..4])).zip(channel(&hex[4..6])).map(|((r, g), b)| (r, g, b)),
+            // #abc is short for #aabbcc
+            3 => channel(&hex[0..1]).zip(channel(&hex[1..2])).zip(channel(&hex[2..3])).map(|((r, g), b)| (r * 17, g * 17, b * 17)),
+            _ => None,
+        };
+        match channels {
+            Some((r, g, b)) => Ok(Color { r, g, b }),
+            None => Err(format!("Invalid color: {}. Use hex like #1a73e8 or #fff", s)),
         }
     }
-    result += "\" fill=\"#000000\"/>\n";
-    result += "</svg>\n";
-    result
 }
 
 
-// Prints the given QrCode object to the console.
-fn print_qr(qr: &QrCode) {
-    let border: i32 = 4;
-    for y in -border .. qr.size() + border {
-        for x in -border .. qr.size() + border {
-            let c: char = if qr.get_module(x, y) { '█' } else { ' ' };
-            print!("{0}{0}", c);
+// A fill for SVG output: a hex color, the CSS currentColor, or none for no fill at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Paint {
+    Hex(Color),
+    CurrentColor,
+    None,
+}
+
+impl Paint {
+    // The color for raster and terminal output, which cannot inherit from CSS.
+    fn color(self) -> Option<Color> {
+        match self {
+            Paint::Hex(color) => Some(color),
+            _ => None,
+        }
+    }
+
+    // The value of an SVG fill attribute.
+    fn svg(self) -> String {
+        match self {
+            Paint::Hex(Color { r, g, b }) => format!("#{:02X}{:02X}{:02X}", r, g, b),
+            Paint::CurrentColor => "currentColor".to_string(),
+            Paint::None => "none".to_string(),
         }
-        println!();
     }
-    println!();
 }
 
+impl std::str::FromStr for Paint {
+    type Err = String;
 
-// Writes the given QrCode object to a PNG image with the specified scale and border width.
-fn write_to_png_scaled(qr: &QrCode, border: i32, scale_factor: u32, file_path: &Path) -> Result<(), String> {
-    // Validate inputs
-    if border < 0 {
-        return Err("Border must be non-negative".to_string());
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "currentcolor" => Ok(Paint::CurrentColor),
+            "none" => Ok(Paint::None),
+            _ => s.parse::<Color>()
+                .map(Paint::Hex)
+                .map_err(|_| format!("Invalid color: {}. Use hex like #1a73e8 or #fff, currentColor or none", s)),
+        }
     }
-    if scale_factor < 1 {
-        return Err("Scale factor must be positive".to_string());
+}
+
+
+// Returns the --fg and --bg colors, swapped with their defaults filled in for --invert.
+fn paints(args: &Cli) -> (Option<Paint>, Option<Paint>) {
+    if args.invert {
+        (Some(args.bg.unwrap_or(Paint::Hex(Color::WHITE))), Some(args.fg.unwrap_or(Paint::Hex(Color::BLACK))))
+    } else {
+        (args.fg, args.bg)
     }
+}
 
-    // Calculate image size
-    let size: i32 = qr.size();
-    let img_size: u32 = (size + 2 * border) as u32;
-    let mut img: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_pixel(img_size, img_size, Luma([255u8]));
 
-    // Draw QR code onto the image
-    for y in 0..size {
-        for x in 0..size {
-            if qr.get_module(x, y) {
-                img.put_pixel((x + border) as u32, (y + border) as u32, Luma([0u8]));
+// Returns the colors of the top-left, top-right and bottom-left finder patterns,
+// each from its own option or else --eye-color.
+fn eye_colors(args: &Cli) -> [Option<Color>; 3] {
+    [args.eye_color_tl, args.eye_color_tr, args.eye_color_bl].map(|color| color.or(args.eye_color))
+}
+
+
+// A gradient for the dark modules, running between two colors across the symbol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Gradient {
+    kind: GradientKind,
+    from: Color,
+    to: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GradientKind {
+    // Along a line at the angle in degrees, clockwise from left to right
+    Linear(f64),
+    // Outwards from the center
+    Radial,
+}
+
+impl Gradient {
+    // Returns how far along the gradient, from 0 to 1, a point is. The point is given as
+    // shares of the symbol's width from its top-left corner; the corners reach the end color.
+    fn offset(&self, u: f64, v: f64) -> f64 {
+        let (dx, dy) = (u - 0.5, v - 0.5);
+        let t: f64 = match self.kind {
+            GradientKind::Linear(angle) => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                (dx * cos + dy * sin) / (cos.abs() + sin.abs()) + 0.5
+            }
+            GradientKind::Radial => dx.hypot(dy) / 0.5f64.sqrt(),
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    // Returns the color at a point given as in `offset`, interpolated in sRGB like SVG does.
+    fn color_at(&self, u: f64, v: f64) -> Color {
+        let t: f64 = self.offset(u, v);
+        let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        Color { r: mix(self.from.r, self.to.r), g: mix(self.from.g, self.to.g), b: mix(self.from.b, self.to.b) }
+    }
+
+    // Returns the SVG element for the gradient over a symbol at (left, top) that is `side` wide.
+    fn svg(&self, id: &str, left: f64, top: f64, side: f64) -> String {
+        let stops: String = format!("<stop offset=\"0\" stop-color=\"{}\"/><stop offset=\"1\" stop-color=\"{}\"/>",
+            Paint::Hex(self.from).svg(), Paint::Hex(self.to).svg());
+        let (cx, cy) = (left + side / 2.0, top + side / 2.0);
+        match self.kind {
+            GradientKind::Linear(angle) => {
+                // The line through the center whose ends are level with the farthest corners
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let half: f64 = (cos.abs() + sin.abs()) * side / 2.0;
+                format!("<linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">{}</linearGradient>",
+                    id, svg_number(cx - cos * half), svg_number(cy - sin * half), svg_number(cx + cos * half), svg_number(cy + sin * half), stops)
             }
+            GradientKind::Radial => format!("<radialGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" cx=\"{}\" cy=\"{}\" r=\"{}\">{}</radialGradient>",
+                id, svg_number(cx), svg_number(cy), svg_number(side * 0.5f64.sqrt()), stops),
         }
     }
+}
 
-    // Scale the image
-    let scaled_img: ImageBuffer<Luma<u8>, Vec<u8>> = image::imageops::resize(&img, img_size * scale_factor, img_size * scale_factor, FilterType::Nearest);
+impl std::str::FromStr for Gradient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let kind: GradientKind = match (parts[0].to_lowercase().as_str(), parts.len()) {
+            ("linear", 3) => GradientKind::Linear(0.0),
+            ("linear", 4) => GradientKind::Linear(parts[3].trim().trim_end_matches("deg").parse::<f64>()
+                .map_err(|_| format!("Invalid gradient angle: {}", parts[3]))?),
+            ("radial", 3) => GradientKind::Radial,
+            _ => return Err(format!("Unknown gradient: {}. Use linear:#ff0000:#0000ff[:angle] or radial:#ff0000:#0000ff", s)),
+        };
+        Ok(Gradient { kind, from: parts[1].parse()?, to: parts[2].parse()? })
+    }
+}
+
+
+// Languages --codegen writes the module matrix in.
+#[derive(Debug, Clone, Copy)]
+enum Language {
+    Rust,
+    C,
+}
+
+impl std::str::FromStr for Language {
+    type Err = String;
 
-    // Save the scaled image as a PNG file
-    scaled_img.save(file_path).map_err(|e| format!("Failed to save PNG file: {}", e))
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rust" | "rs" => Ok(Language::Rust),
+            "c" => Ok(Language::C),
+            _ => Err(format!("Unknown language: {}. Use rust or c", s)),
+        }
+    }
+}
+
+
+fn main() -> io::Result<()> {
+    trace::init();
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if args.build_info {
+        build_info::print();
+        return Ok(());
+    }
+
+    // -o code.svg means SVG unless --output-type names another type
+    let explicit_type: bool = matches.value_source("output_type") == Some(ValueSource::CommandLine);
+    if args.codegen.is_some() {
+        // Source code is written as text whatever the file is called
+    } else if args.output_type == OutputType::Auto || (!explicit_type && args.output_file.is_some() && !writes_stdout(&args)) {
+        let Some(path) = args.output_file.as_deref() else {
+            eprintln!("--output-type auto needs an --output-file to take the type from.");
+            std::process::exit(1);
+        };
+        args.output_type = OutputType::from_extension(path).unwrap_or_else(|| {
+            eprintln!("Cannot tell the output type from '{}'. Use a .txt, .svg, .png, .jpg, .webp, .html, .json, .six, .raw, .pbm, .pgm, .xbm, .h, .pdf, .eps or .dxf file, or set --output-type.",
+                path.display());
+            std::process::exit(1);
+        });
+    } else if let Some(path) = args.output_file.as_deref()
+        && let Some(inferred) = OutputType::from_extension(path)
+        && inferred != args.output_type
+    {
+        eprintln!("Warning: writing {:?} output to '{}', which has a {:?} extension.", args.output_type, path.display(), inferred);
+    }
+
+    match &args.command {
+        Some(Command::Capacities { version, mode }) => {
+            print_capacities(*version, *mode);
+            return Ok(());
+        }
+        Some(Command::Sheet(sheet_args)) => return sheet::run(&args, sheet_args),
+        #[cfg(feature = "server")]
+        Some(Command::Serve { addr }) => return server::serve(addr),
+        None => {}
+    }
+
+    let mut levels: Vec<QrCodeEcc> = match parse_ecc_levels(&args.ecc) {
+        Some(levels) => levels,
+        None => {
+            eprintln!("Invalid error correction level. Use L, M, Q, or H.");
+            return Ok(());
+        }
+    };
+
+    // Boosting would raise every listed level to the same highest one that fits, giving identical files
+    if levels.len() > 1 {
+        args.no_boost_ecc = true;
+    }
+
+    // A logo hides modules and a blended picture most of each one, which only the highest level reliably recovers
+    let covered: Option<&str> = if args.logo.is_some() {
+        Some("the logo hides part of the code")
+    } else if args.blend_image.is_some() {
+        Some("the blended picture covers most of each module")
+    } else {
+        None
+    };
+    if let Some(covered) = covered && levels.iter().any(|&ecc| ecc != QrCodeEcc::High) {
+        if matches.value_source("ecc") == Some(ValueSource::CommandLine) {
+            eprintln!("Warning: {}. Use --ecc H so it stays scannable.", covered);
+        } else {
+            levels = vec![QrCodeEcc::High];
+        }
+    }
+
+    // A card gets shared and printed, so it uses the most robust level and the plain rounded frame
+    if args.url_card {
+        if !matches!(args.output_type, OutputType::PNG | OutputType::SVG) {
+            eprintln!("--url-card writes PNG or SVG output.");
+            std::process::exit(1);
+        }
+        if matches.value_source("ecc") != Some(ValueSource::CommandLine) {
+            levels = vec![QrCodeEcc::High];
+        }
+        args.frame = Some(FrameStyle::Simple);
+    }
+
+    if let (Some(min), Some(max)) = (args.min_version, args.max_version) && min > max {
+        eprintln!("--min-version must not be larger than --max-version.");
+        std::process::exit(1);
+    }
+
+    if args.border_width < 0.0 || args.scale <= 0.0 {
+        eprintln!("Border width must be non-negative and scale must be positive.");
+        std::process::exit(1);
+    }
+
+    if args.bg_radius < 0.0 || args.bg_bleed < 0.0 {
+        eprintln!("Background radius and bleed must be non-negative.");
+        std::process::exit(1);
+    }
+
+    if (args.bg_radius > 0.0 || args.bg_bleed > 0.0) && !matches!(args.output_type, OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --bg-radius and --bg-bleed are only used for SVG output.");
+    }
+
+    if !(0.0..=0.5).contains(&args.corner_radius) {
+        eprintln!("The corner radius must be between 0 and 0.5.");
+        std::process::exit(1);
+    }
+
+    if args.dot_radius <= 0.0 || args.dot_radius > 0.5 {
+        eprintln!("The dot radius must be above 0 and at most 0.5.");
+        std::process::exit(1);
+    }
+
+    if args.shape != Shape::Square && !matches!(args.output_type, OutputType::SVG | OutputType::SVGDATAURI | OutputType::PNG) {
+        eprintln!("Warning: --shape is only used for SVG and PNG output.");
+    }
+
+    if args.font_size.is_some_and(|size| size <= 0.0) {
+        eprintln!("The font size must be positive.");
+        std::process::exit(1);
+    }
+
+    if args.caption.is_some() && !matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI | OutputType::PDF) {
+        eprintln!("Warning: --caption is only used for PNG, SVG and PDF output.");
+    }
+
+    if args.blend_image.is_some() && !matches!(args.output_type, OutputType::PNG) {
+        eprintln!("Warning: --blend-image is only used for PNG output.");
+    }
+
+    if args.frame.is_some() && !matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --frame is only used for PNG and SVG output.");
+    }
+
+    if (args.eye_shape.is_some() || eye_colors(&args).iter().any(Option::is_some)) && !matches!(args.output_type, OutputType::SVG | OutputType::SVGDATAURI | OutputType::PNG) {
+        eprintln!("Warning: --eye-shape and the --eye-color options are only used for SVG and PNG output.");
+    }
+
+    if (args.page.is_some() || args.margin.is_some()) && !matches!(args.output_type, OutputType::PDF) {
+        eprintln!("Warning: --page and --margin are only used for PDF output.");
+    }
+
+    if matches.value_source("quality") == Some(ValueSource::CommandLine) && !matches!(args.output_type, OutputType::JPEG) {
+        eprintln!("Warning: --quality is only used for JPEG output.");
+    }
+
+    if args.compact {
+        args.glyphs = Glyphs::Half;
+    }
+
+    if args.braille_grid == BrailleGrid::TwoByThree {
+        if !matches!(args.glyphs, Glyphs::Braille) {
+            eprintln!("Warning: --braille-grid is only used with --glyphs braille.");
+        } else {
+            args.glyphs = Glyphs::SixDotBraille;
+        }
+    }
+
+    if args.ansi && matches!(args.glyphs, Glyphs::Quadrant | Glyphs::Braille | Glyphs::SixDotBraille) {
+        eprintln!("ANSI output draws with the block or half glyphs.");
+        std::process::exit(1);
+    }
+
+    if args.ansi && !matches!(args.output_type, OutputType::TXT) {
+        eprintln!("Warning: --ansi is only used for Text output.");
+    }
+
+    let colored: bool = args.fg.is_some() || args.bg.is_some();
+    if colored && matches!(args.output_type, OutputType::TXT) && !args.ansi {
+        eprintln!("Warning: --fg and --bg color Text output only together with --ansi.");
+    } else if colored && !matches!(args.output_type, OutputType::TXT | OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --fg and --bg are only used for PNG, SVG and ANSI Text output.");
+    }
+
+    if args.transparent && !matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --transparent is only used for PNG and SVG output.");
+    }
+
+    if args.gradient.is_some() && !matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --gradient is only used for PNG and SVG output.");
+    }
+
+    if args.logo.is_some() && !matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) {
+        eprintln!("Warning: --logo is only used for PNG and SVG output.");
+    }
+
+    if args.fg == Some(Paint::None) {
+        eprintln!("The dark modules need a color; only --bg can be none.");
+        std::process::exit(1);
+    }
+
+    if args.invert && args.bg == Some(Paint::None) {
+        eprintln!("Inverted codes draw the modules in the --bg color, so it can't be none.");
+        std::process::exit(1);
+    }
+
+    if args.invert && !args.quiet {
+        eprintln!("Warning: some scanners do not read inverted codes, with light modules on a dark background. Use --quiet to hide this warning.");
+    }
+
+    if args.invert && (args.codegen.is_some() || matches!(args.output_type, OutputType::MATRIX | OutputType::MATRIXTEXT | OutputType::DXF)) {
+        eprintln!("Warning: --invert is not used for Matrix, Matrix-Text, DXF and --codegen output.");
+    }
+
+    let svg_only: bool = [args.fg, args.bg].iter().flatten().any(|paint| paint.color().is_none());
+    if svg_only && matches!(args.output_type, OutputType::TXT | OutputType::PNG) {
+        eprintln!("currentColor and none only work for SVG output; use a hex color instead.");
+        std::process::exit(1);
+    }
+
+    // Scanners only see luminance, so every color of the dark modules needs enough contrast with the light one
+    if colored || args.gradient.is_some() || eye_colors(&args).iter().any(Option::is_some) {
+        let (fg, bg) = paints(&args);
+        let darks: Vec<Color> = [fg.unwrap_or(Paint::Hex(Color::BLACK)).color()].into_iter()
+            .chain(eye_colors(&args))
+            .flatten()
+            .chain(args.gradient.iter().flat_map(|gradient| [gradient.from, gradient.to]))
+            .collect();
+        let light: Option<Color> = if args.transparent { None } else { bg.unwrap_or(Paint::Hex(Color::WHITE)).color() };
+        if let Some(light) = light && let Some(contrast) = darks.iter().map(|dark| dark.contrast(light)).min_by(f64::total_cmp) {
+            if args.verbose {
+                eprintln!("Contrast ratio of the dark and light colors: {:.2}:1", contrast);
+            }
+            if contrast < MIN_CONTRAST {
+                let message: String = format!("the contrast ratio of the dark and light colors is {:.2}:1, below the {}:1 scanners need", contrast, MIN_CONTRAST);
+                if refuses_contrast(args.force_colors, args.strict) {
+                    eprintln!("Refusing colors: {}.{}", message, if args.strict { "" } else { " Use --force-colors to keep them anyway." });
+                    std::process::exit(1);
+                }
+                eprintln!("Warning: {}.", message);
+            }
+        }
+    }
+
+    if args.module_size_mm <= 0.0 {
+        eprintln!("The module size must be positive.");
+        std::process::exit(1);
+    }
+
+    if args.dpi.is_some_and(|dpi| dpi <= 0.0) {
+        eprintln!("The dpi must be positive.");
+        std::process::exit(1);
+    }
+
+    let printed: bool = args.physical.is_some() || args.module_size.is_some();
+    if args.dpi.is_some() && !printed && !matches!(args.output_type, OutputType::PNG) {
+        eprintln!("Warning: --dpi is only used for PNG output and with --physical or --module-size.");
+    }
+
+    let vector: bool = matches!(args.output_type, OutputType::SVG | OutputType::SVGDATAURI | OutputType::PDF | OutputType::EPS | OutputType::DXF);
+    if printed && args.dpi.is_none() && !vector {
+        eprintln!("Raster output needs --dpi to turn a printed size into pixels.");
+        std::process::exit(1);
+    }
+
+    if args.embed_metadata && !matches!(args.output_type, OutputType::PNG) {
+        eprintln!("Warning: --embed-metadata is only used for PNG output.");
+    }
+
+    if args.html_fragment && !matches!(args.output_type, OutputType::HTML) {
+        eprintln!("Warning: --html-fragment is only used for HTML output.");
+    }
+
+    if args.bg_image.is_some() && !matches!(args.output_type, OutputType::PNG) {
+        eprintln!("Warning: --bg-image is only used for PNG output.");
+    }
+
+    // Text output always draws a full quiet zone, and a background image is checked when compositing
+    let composited: bool = args.bg_image.is_some() && matches!(args.output_type, OutputType::PNG);
+    let quiet_zone: i32 = match args.symbology {
+        Symbology::Qr => QUIET_ZONE,
+        Symbology::MicroQr => micro::QUIET_ZONE,
+        Symbology::Rmqr => rmqr::QUIET_ZONE,
+    };
+    if !matches!(args.output_type, OutputType::TXT) && !composited && args.border_width < quiet_zone as f64 {
+        report_quiet_zone(&format!("the border of {} modules intrudes {} modules into the {}-module quiet zone.",
+            args.border_width, quiet_zone as f64 - args.border_width, quiet_zone), args.strict);
+    }
+
+    if args.symbology != Symbology::Qr {
+        // Pictures over the modules need the decode check, which only reads QR codes, and --codegen writes QR matrices
+        if args.logo.is_some() || args.bg_image.is_some() || args.blend_image.is_some() || args.codegen.is_some() {
+            eprintln!("--logo, --bg-image, --blend-image and --codegen only work for QR codes.");
+            std::process::exit(1);
+        }
+        if !matches!(args.output_type, OutputType::TXT | OutputType::SVG | OutputType::PNG | OutputType::PGM) {
+            eprintln!("Micro QR and rMQR codes can only be written as Text, SVG, PNG or PGM.");
+            std::process::exit(1);
+        }
+        if args.structured_append || args.gs1 || (args.charset.is_some() && args.symbology == Symbology::MicroQr) {
+            eprintln!("--structured-append and --gs1 only work for QR codes, and --charset for QR and rMQR codes.");
+            std::process::exit(1);
+        }
+    }
+    if let Some(height) = args.rmqr_height {
+        if !rmqr::HEIGHTS.contains(&height) {
+            eprintln!("Unknown rMQR height: {}. Use 7, 9, 11, 13, 15 or 17", height);
+            std::process::exit(1);
+        }
+        if args.symbology != Symbology::Rmqr {
+            eprintln!("Warning: --rmqr-height is only used with --symbology rmqr.");
+        }
+    }
+
+    // A tar archive keeps every output apart, whatever its type
+    if writes_stdout(&args) && args.output_type.needs_file() && io::stdout().is_terminal() {
+        eprintln!("Refusing to write {:?} output to a terminal. Pipe or redirect it, or give a file name.", args.output_type);
+        std::process::exit(1);
+    }
+
+    let separate_files: bool = args.output_type.needs_file() && !writes_stdout(&args);
+    if (levels.len() > 1 || args.null_separated) && args.tar.is_none() && !(matches!(args.output_type, OutputType::TXT) || separate_files) {
+        eprintln!("Multiple error correction levels or records need Text output, a file type like PNG or PDF, or --tar.");
+        std::process::exit(1);
+    }
+
+    let output_file: PathBuf = match &args.output_dir {
+        Some(dir) => {
+            if writes_file(&args) || args.jsonl {
+                std::fs::create_dir_all(dir)?;
+            }
+            dir.join(default_output_file(&args).file_name().unwrap_or_default())
+        }
+        None => default_output_file(&args),
+    };
+
+    if args.jsonl {
+        return jsonl::run(&args, &output_file);
+    }
+
+    let data: Vec<u8> = match &args.file {
+        // Structured append is for data larger than one code, so the file isn't held to that size
+        Some(path) if args.structured_append => read_input(&Some(path.clone()), false)?,
+        Some(path) => read_file(path, &levels)?,
+        None => read_input(&args.input, args.stdin)?,
+    };
+
+    // The card's caption is the URL as it is encoded
+    if args.url_card {
+        let url: String = String::from_utf8_lossy(&data).into_owned() + args.append.as_deref().unwrap_or_default();
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            eprintln!("Warning: --url-card expects a URL starting with https:// or http://.");
+        }
+        args.caption = Some(url);
+    }
+
+    let records: Vec<&[u8]> = if args.null_separated {
+        split_records(&data)
+    } else {
+        vec![&data]
+    };
+
+    let mut archive: Option<Archive> = match &args.tar {
+        Some(path) => Some(open_archive(path, args.force)?),
+        None => None,
+    };
+
+    for (index, record) in records.iter().enumerate() {
+        // Each record gets its own numbered file, e.g. qrcode-1.png
+        let result: io::Result<()> = if args.null_separated {
+            if args.verbose {
+                eprintln!("Record {}:", index + 1);
+            }
+            encode_payload(record, &levels, &args, &suffixed_path(&output_file, &(index + 1).to_string()), &mut archive)
+        } else {
+            encode_payload(record, &levels, &args, &output_file, &mut archive)
+        };
+        // A file that could not be written or rendered ends the run, so scripts see it in the exit status
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(archive) = archive {
+        archive.into_inner()?.flush()?;
+    }
+
+    Ok(())
+}
+
+
+// Encodes one payload at every requested error correction level and writes the results.
+fn encode_payload(data: &[u8], levels: &[QrCodeEcc], args: &Cli, output_file: &Path, archive: &mut Option<Archive>) -> io::Result<()> {
+    let decoded: Option<Vec<u8>> = args.input_encoding.map(|encoding| encoding.decode(data).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }));
+    let data: &[u8] = decoded.as_deref().unwrap_or(data);
+
+    // File contents, --binary input and decoded bytes are encoded byte for byte; everything else must be text
+    let raw: bool = args.file.is_some() || args.binary || decoded.is_some();
+    let mut text: String = if raw {
+        String::from_utf8_lossy(data).into_owned()
+    } else {
+        match String::from_utf8(data.to_vec()) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Input is not valid UTF-8 text: {}. Use --binary to encode it byte for byte.", e.utf8_error());
+                std::process::exit(1);
+            }
+        }
+    };
+    let input: String = text.clone();
+    if let Some(append) = &args.append {
+        text.push_str(append);
+    }
+    if let Some(charset) = args.charset && let Err(c) = charset.encode(&text) {
+        eprintln!("The input has a character that is not in the --charset: '{}'.", c);
+        std::process::exit(1);
+    }
+
+    let elements: Option<String> = args.gs1.then(|| gs1::element_string(&text).unwrap_or_else(|e| {
+        eprintln!("Invalid GS1 data: {}", e);
+        std::process::exit(1);
+    }));
+
+    // Each part picks its own modes, so a numeric tag
+    // doesn't force the whole payload into byte mode
+    let charset: Charset = args.charset.unwrap_or_default();
+    let segments = |version: u8| -> Vec<QrSegment> {
+        if let Some(elements) = &elements {
+            return gs1::make_segments(elements, version, !args.no_optimize);
+        }
+        // A charset that was asked for is announced before the data, even UTF-8
+        let mut segs: Vec<QrSegment> = args.charset.map(|charset| QrSegment::make_eci(charset.eci())).into_iter().collect();
+        if raw {
+            segs.push(QrSegment::make_bytes(data));
+        } else {
+            segs.extend(segment::make_segments(&input, version, !args.no_optimize, charset));
+        }
+        if let Some(append) = &args.append {
+            segs.extend(segment::make_segments(append, version, !args.no_optimize, charset));
+        }
+        segs
+    };
+
+    // The preview picks its own version, so it gets the segments for the smallest ones
+    #[cfg(feature = "tui")]
+    if args.tui {
+        let (border, _) = raster_size(args.border_width, args.scale);
+        return tui::run(&segments(Version::MIN.value()), levels[0], border, args, output_file);
+    }
+
+    for &ecc in levels {
+        trace::stage!("encode", ecc = ?ecc);
+        // Each level gets its own file, e.g. qrcode-L.png
+        let level_file: PathBuf = if levels.len() > 1 {
+            suffixed_path(output_file, &spec::ecc_letter(ecc).to_string())
+        } else {
+            output_file.to_path_buf()
+        };
+        let min_version: Version = Version::new(args.min_version.unwrap_or(Version::MIN.value()));
+        let max_version: Version = Version::new(args.max_version.unwrap_or(Version::MAX.value()));
+        let mask: Option<Mask> = args.mask.map(Mask::new);
+
+        if args.symbology == Symbology::MicroQr {
+            let payload: Vec<u8> = if raw { [data, args.append.as_deref().unwrap_or_default().as_bytes()].concat() } else { text.clone().into_bytes() };
+            match micro::MicroCode::encode(&payload, ecc) {
+                Ok(code) => {
+                    if args.verbose {
+                        eprintln!("Version: {} (ECC {})", code.version_name(), spec::ecc_letter(code.error_correction_level()));
+                    }
+                    write_symbol(&code, &text, &payload, args, &level_file, levels.len() > 1 || args.null_separated, archive)?;
+                }
+                Err(e) => eprintln!("Failed to generate QR code: {}", e),
+            }
+            continue;
+        }
+
+        if args.symbology == Symbology::Rmqr {
+            let segs: Vec<QrSegment> = segments(Version::MIN.value());
+            match rmqr::RmqrCode::encode(&segs, ecc, args.rmqr_height, !args.no_boost_ecc) {
+                Ok(code) => {
+                    if args.verbose {
+                        eprintln!("Version: {} (ECC {})", code.version_name(), spec::ecc_letter(code.error_correction_level()));
+                    }
+                    write_symbol(&code, &text, &segment::decoded_bytes(&segs), args, &level_file, levels.len() > 1 || args.null_separated, archive)?;
+                }
+                Err(e) => eprintln!("Failed to generate QR code: {}", e),
+            }
+            continue;
+        }
+
+        if args.structured_append {
+            let symbols: Vec<(QrCode, Vec<u8>)> = structured::encode(data, !raw, ecc, min_version, max_version, mask, !args.no_boost_ecc)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to generate QR code: {}", e);
+                    std::process::exit(1);
+                });
+            // Every symbol gets its own numbered file, e.g. qrcode-1.png
+            for (index, (qr, part)) in symbols.iter().enumerate() {
+                if args.verbose {
+                    eprintln!("Symbol {} of {}: version {}, ECC {}, {} bytes",
+                        index + 1, symbols.len(), qr.version().value(), spec::ecc_letter(qr.error_correction_level()), part.len());
+                }
+                if args.stats {
+                    print_stats(qr);
+                }
+                if args.dump_matrix {
+                    print_matrix(qr);
+                }
+                let symbol_file: PathBuf = suffixed_path(&level_file, &(index + 1).to_string());
+                write_symbol(qr, &String::from_utf8_lossy(part), part, args, &symbol_file, true, archive)?;
+            }
+            continue;
+        }
+
+        // Attempt to encode the segments into a QR code, at the smallest version in range that fits
+        let encoded: Result<(QrCode, Vec<QrSegment>), DataTooLong> = if args.gs1 {
+            gs1::encode(segments, ecc, min_version, max_version, mask, !args.no_boost_ecc)
+        } else {
+            segment::encode(segments, ecc, min_version, max_version, mask, !args.no_boost_ecc)
+        };
+        match encoded {
+            Ok((qr, segs)) => {
+                trace::debug!(version = qr.version().value(), mask = qr.mask().value(), "encoded");
+                if args.verbose {
+                    let version: u8 = qr.version().value();
+                    print_segments(&segs, version);
+                    let fnc1: usize = if args.gs1 { gs1::FNC1_BITS } else { 0 };
+                    let used: usize = fnc1 + segs.iter().map(|seg| spec::segment_bits(seg, version)).sum::<usize>();
+                    eprintln!("Version: {} ({} of {} data bits used)",
+                        version, used, spec::num_data_codewords(version, qr.error_correction_level()) * 8);
+                }
+                if levels.len() > 1 {
+                    eprintln!("ECC {}: version {} (encoded at ECC {})",
+                        spec::ecc_letter(ecc), qr.version().value(), spec::ecc_letter(qr.error_correction_level()));
+                }
+                if args.stats {
+                    print_stats(&qr);
+                }
+                if args.mask_penalty {
+                    print_mask_penalties(&qr, &segs);
+                }
+                if args.dump_matrix {
+                    print_matrix(&qr);
+                }
+                write_symbol(&qr, &text, &segment::decoded_bytes(&segs), args, &level_file, levels.len() > 1 || args.null_separated, archive)?;
+            }
+            Err(e) => {
+                // A capped version is a hard limit, so nothing else is tried
+                if let Some(max) = args.max_version {
+                    eprintln!("Failed to generate QR code: the data does not fit in version {} at ECC {} ({}).", max, spec::ecc_letter(ecc), e);
+                    std::process::exit(1);
+                }
+                eprintln!("Failed to generate QR code: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+// Writes one code at the scale asked for, unless its file is taken and gets skipped.
+// The payload is what a scanner reads back, to check images that may not scan.
+fn write_symbol(qr: &impl Grid, text: &str, payload: &[u8], args: &Cli, output_file: &Path, batch: bool, archive: &mut Option<Archive>) -> io::Result<()> {
+    let scale: f64 = match (printed_size(qr, args), args.size) {
+        (Some(inches), _) => physical_scale(qr, args, inches),
+        (None, Some(pixels)) => pixel_scale(qr, args, pixels),
+        (None, None) => args.scale,
+    };
+    match resolve_collision(output_file, args, batch) {
+        Some(output_file) => write_output(qr, text, payload, args, &output_file, scale, archive),
+        None => Ok(()),
+    }
+}
+
+
+// Renders the QR code in the requested output type.
+// PNG always goes to the given file; the other types only do if --output-file was given
+// and are printed to stdout otherwise. With --tar everything becomes an archive entry instead.
+fn write_output(qr: &impl Grid, text: &str, payload: &[u8], args: &Cli, output_file: &Path, scale: f64, archive: &mut Option<Archive>) -> io::Result<()> {
+    trace::stage!("render", output_type = ?args.output_type, file = %output_file.display());
+    // Logos, --codegen and matrix output are refused for Micro QR and rMQR up front
+    if args.logo.is_some() && matches!(args.output_type, OutputType::PNG | OutputType::SVG | OutputType::SVGDATAURI) && let Some(qr) = qr.qr_code() {
+        check_logo_area(qr, logo_area(qr, args.logo_size), args.strict);
+    }
+    if let Some(language) = args.codegen && let Some(qr) = qr.qr_code() {
+        return emit_output(&to_matrix_source(qr, language, &c_identifier(output_file)).into_bytes(), args, output_file, archive);
+    }
+    // Terminal images fall back to text where the terminal can't show them
+    if let Some(protocol) = args.output_type.protocol() && !terminal::use_graphics(protocol, args.graphics) {
+        if args.graphics == GraphicsMode::Auto {
+            eprintln!("Warning: the terminal does not seem to show {:?} images, printing text instead. Use --graphics always to send them anyway.", protocol);
+        }
+        return emit_output(format!("{}\n", to_text_string(qr, qr.quiet_zone(), args.invert, args.glyphs)).as_bytes(), args, output_file, archive);
+    }
+    let contents: Vec<u8> = match args.output_type {
+        OutputType::Auto => unreachable!("the output type is resolved before use"),
+        OutputType::TXT if args.ansi => {
+            // Either color alone still switches to 24-bit color, with the usual other one
+            let (fg, bg) = paints(args);
+            let colors: Option<(Color, Color)> = match (fg.and_then(Paint::color), bg.and_then(Paint::color)) {
+                (None, None) => None,
+                (fg, bg) => Some((fg.unwrap_or(Color::BLACK), bg.unwrap_or(Color::WHITE))),
+            };
+            format!("{}\n", to_ansi_string(qr, qr.quiet_zone(), matches!(args.glyphs, Glyphs::Half), colors)).into_bytes()
+        }
+        OutputType::TXT => format!("{}\n", to_text_string(qr, qr.quiet_zone(), args.invert, args.glyphs)).into_bytes(),
+        OutputType::SVG => {
+            format!("{}\n", to_svg_string(qr, args.border_width, scale, &SvgStyle { physical: printed_size(qr, args), ..SvgStyle::from_args(args) })).into_bytes()
+        }
+        OutputType::PNG => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            let mut info: PngInfo = if args.embed_metadata { PngInfo::describing(qr, text) } else { PngInfo::default() };
+            let (fg, bg) = paints(args);
+            info.dark = fg.and_then(Paint::color).unwrap_or(Color::BLACK);
+            info.light = bg.and_then(Paint::color).unwrap_or(Color::WHITE);
+            info.transparent = args.transparent;
+            info.dpi = args.dpi;
+            info.size = args.size;
+            info.gradient = args.gradient;
+            info.modules = ModuleStyle::from_args(args);
+            info.caption = Caption::from_args(args);
+            info.frame = Frame::from_args(args);
+            if let Some(path) = &args.blend_image {
+                let blend: Result<Blend, String> = if (scale as u32) < halftone::SUBDIVISION {
+                    Err(format!("--blend-image needs a scale of at least {} pixels per module.", halftone::SUBDIVISION))
+                } else {
+                    Blend::new(path, qr)
+                };
+                match blend {
+                    Ok(blend) => info.blend = Some(blend),
+                    Err(e) => return Err(io::Error::other(format!("Error writing PNG: {}", e))),
+                }
+                if !decodes_to(&to_rgba_image(qr, border, scale as u32, &info), payload) {
+                    eprintln!("Warning: the QR code could not be decoded with the picture blended in. Try a picture with more contrast.");
+                }
+            }
+            let result = match (&args.bg_image, &args.logo) {
+                (Some(bg_path), _) => to_png_on_background(qr, payload, border, scale as u32, bg_path, args.strict, &info),
+                (None, Some(logo)) => to_png_with_logo(qr, payload, border, scale as u32, logo, args.logo_size, &info),
+                (None, None) => to_png_bytes(qr, border, scale as u32, &info),
+            };
+            match result {
+                Ok(bytes) => bytes,
+                Err(e) => return Err(io::Error::other(format!("Error writing PNG: {}", e))),
+            }
+        }
+        OutputType::JPEG => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_jpeg_bytes(qr, border, scale as u32, args.quality, args.invert) {
+                Ok(bytes) => bytes,
+                Err(e) => return Err(io::Error::other(format!("Error writing JPEG: {}", e))),
+            }
+        }
+        OutputType::WEBP => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_webp_bytes(qr, border, scale as u32, args.invert) {
+                Ok(bytes) => bytes,
+                Err(e) => return Err(io::Error::other(format!("Error writing WebP: {}", e))),
+            }
+        }
+        OutputType::HTML => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            to_html_string(qr, border, scale, text, args.html_fragment, args.invert).into_bytes()
+        }
+        OutputType::SIXEL => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_luma_image(qr, border, scale as u32, args.invert) {
+                Ok(image) => format!("{}\n", terminal::to_sixel_string(&image)).into_bytes(),
+                Err(e) => return Err(io::Error::other(format!("Error writing Sixel: {}", e))),
+            }
+        }
+        OutputType::DATAURI => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_png_bytes(qr, border, scale as u32, &PngInfo::plain(args.invert)) {
+                Ok(bytes) => format!("{}\n", to_data_uri("image/png", &bytes)).into_bytes(),
+                Err(e) => return Err(io::Error::other(format!("Error writing data URI: {}", e))),
+            }
+        }
+        OutputType::SVGDATAURI => {
+            let svg: String = to_svg_string(qr, args.border_width, scale, &SvgStyle { physical: printed_size(qr, args), ..SvgStyle::from_args(args) });
+            format!("{}\n", to_data_uri("image/svg+xml", svg.as_bytes())).into_bytes()
+        }
+        OutputType::MATRIX => match qr.qr_code() {
+            Some(qr) => format!("{}\n", to_matrix_json(qr)).into_bytes(),
+            None => unreachable!("matrix output is refused for Micro QR and rMQR"),
+        },
+        OutputType::MATRIXTEXT => (0..qr.height())
+            .map(|y| (0..qr.width()).map(|x| if qr.get_module(x, y) { '1' } else { '0' }).chain(['\n']).collect::<String>())
+            .collect::<String>()
+            .into_bytes(),
+        OutputType::KITTY | OutputType::ITERM2 => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_png_bytes(qr, border, scale as u32, &PngInfo::plain(args.invert)) {
+                Ok(bytes) if args.output_type == OutputType::KITTY => format!("{}\n", terminal::to_kitty_string(&bytes)).into_bytes(),
+                Ok(bytes) => format!("{}\n", terminal::to_iterm2_string(&bytes)).into_bytes(),
+                Err(e) => return Err(io::Error::other(format!("Error writing terminal image: {}", e))),
+            }
+        }
+        OutputType::EPS => to_eps_string(qr, args.border_width, scale, args.invert).into_bytes(),
+        OutputType::DXF => to_dxf_string(qr, args.border_width, args.module_size.map_or(args.module_size_mm, |inches| inches * 25.4)).into_bytes(),
+        OutputType::PDF => {
+            let margin: f64 = args.margin.unwrap_or(0.0) * pdf::POINTS_PER_INCH;
+            match pdf::to_pdf_bytes(qr, args.border_width, scale, args.page, margin, args.invert, Caption::from_args(args).as_ref()) {
+                Ok(bytes) => bytes,
+                Err(e) => return Err(io::Error::other(format!("Error writing PDF: {}", e))),
+            }
+        }
+        OutputType::RAW1 => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            let (width, height) = ((qr.width() + 2 * border) * scale, (qr.height() + 2 * border) * scale);
+            let bitmap: Vec<u8> = to_raw1_bitmap(qr, border, scale, args.invert);
+            if args.verbose {
+                eprintln!("width={} height={} stride={}", width, height, bitmap.len() as i32 / height);
+            }
+            bitmap
+        }
+        OutputType::PBM => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            to_pbm_bytes(qr, border, scale, args.invert)
+        }
+        OutputType::XBM => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            to_xbm_string(qr, border, scale, &c_identifier(output_file), args.invert).into_bytes()
+        }
+        OutputType::C => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            to_c_array_string(qr, border, scale, &c_identifier(output_file), args.invert).into_bytes()
+        }
+        OutputType::PGM => {
+            let (border, scale) = raster_size(args.border_width, scale);
+            match to_luma_image(qr, border, scale as u32, args.invert) {
+                Ok(image) => to_pgm_bytes(&image),
+                Err(e) => return Err(io::Error::other(format!("Error writing PGM: {}", e))),
+            }
+        }
+    };
+
+    emit_output(&contents, args, output_file, archive)
+}
+
+
+// Sends rendered output where it belongs: into the tar archive, to the output file or to stdout.
+fn emit_output(contents: &[u8], args: &Cli, output_file: &Path, archive: &mut Option<Archive>) -> io::Result<()> {
+    if let Some(archive) = archive {
+        // Without -o the entries are still named after the type, e.g. qrcode-1.svg
+        let entry: PathBuf = match &args.output_file {
+            Some(_) => output_file.to_path_buf(),
+            None => output_file.with_extension(args.output_type.extension()),
+        };
+        append_entry(archive, entry.file_name().unwrap_or_default().as_ref(), contents)?;
+    } else if writes_file(args) {
+        std::fs::write(output_file, contents)
+            .map_err(|e| io::Error::new(e.kind(), format!("Error writing '{}': {}", output_file.display(), e)))?;
+    } else {
+        io::stdout().write_all(contents)?;
+    }
+    Ok(())
+}
+
+
+// Returns whether the output goes to a file rather than to stdout or an archive.
+fn writes_file(args: &Cli) -> bool {
+    args.tar.is_none() && !writes_stdout(args) && (args.output_type.needs_file() || args.output_file.is_some())
+}
+
+
+// Returns whether -o - asks for even binary output on stdout, e.g. to pipe a PNG on.
+fn writes_stdout(args: &Cli) -> bool {
+    args.tar.is_none() && args.output_file.as_deref() == Some(Path::new("-"))
+}
+
+
+// A tar archive streamed to stdout or a file as outputs are rendered.
+type Archive = tar::Builder<Box<dyn Write>>;
+
+
+// Starts a tar archive at the given path, or on stdout for "-".
+fn open_archive(path: &Path, force: bool) -> io::Result<Archive> {
+    let writer: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(io::stdout().lock())
+    } else {
+        if let Err(e) = check_overwrite(path, force) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        Box::new(io::BufWriter::new(File::create(path)?))
+    };
+    Ok(tar::Builder::new(writer))
+}
+
+
+// Adds one output to the archive as a regular file, stamped with the current time.
+fn append_entry(archive: &mut Archive, name: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    archive.append_data(&mut header, name, contents)?;
+    // Flush after every entry so consumers can start on it right away
+    archive.get_mut().flush()
+}
+
+
+// Returns the output file path, falling back to qrcode.png, or e.g. qrcode.pdf for other file types.
+fn default_output_file(args: &Cli) -> PathBuf {
+    args.output_file.clone().unwrap_or_else(|| {
+        let default = PathBuf::from(DEFAULT_OUTPUT_FILE);
+        if args.output_type.needs_file() { default.with_extension(args.output_type.extension()) } else { default }
+    })
+}
+
+
+/*---- Utilities ----*/
+
+
+// Read the data to encode. An explicit --stdin wins over --input,
+// which wins over data piped into the standard input.
+// The raw bytes are returned; converting them to text is up to the caller.
+fn read_input(input: &Option<PathBuf>, force_stdin: bool) -> Result<Vec<u8>, io::Error> {
+    let mut data: Vec<u8> = Vec::new();
+
+    if force_stdin {
+        data = read_stdin()?;
+    } else if let Some(file_path) = input {
+        File::open(file_path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| {
+                eprintln!("Error reading file '{}': {}", file_path.display(), e);
+                e
+            })?;
+    } else if !io::stdin().is_terminal() {
+        data = read_stdin()?;
+    } else {
+        eprintln!("No input provided. Please specify a file or pipe data.");
+        std::process::exit(1); // Exit the program with a non-zero status
+    }
+
+    if data.is_empty() {
+        eprintln!("Input is empty. Nothing to encode.");
+        std::process::exit(1);
+    }
+
+    Ok(data)
+}
+
+
+// Reads a file to encode as binary data. Its size is checked against the capacity of
+// a version 40 code at every requested level first, so oversized files fail before being read.
+fn read_file(path: &Path, levels: &[QrCodeEcc]) -> Result<Vec<u8>, io::Error> {
+    let size: u64 = std::fs::metadata(path)
+        .map_err(|e| {
+            eprintln!("Error reading file '{}': {}", path.display(), e);
+            e
+        })?
+        .len();
+
+    for &ecc in levels {
+        let max_bytes: usize = spec::max_chars(QrSegmentMode::Byte, 40, ecc);
+        if size > max_bytes as u64 {
+            eprintln!("File '{}' is {} bytes, but a QR code at ECC {} holds at most {} bytes.",
+                path.display(), size, spec::ecc_letter(ecc), max_bytes);
+            std::process::exit(1);
+        }
+    }
+
+    read_input(&Some(path.to_path_buf()), false)
+}
+
+
+// Read the standard input until EOF.
+// FIFOs and some redirections may deliver data late or in bursts,
+// so transient errors are retried instead of being taken as the end of input.
+fn read_stdin() -> Result<Vec<u8>, io::Error> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let mut stdin = io::stdin().lock();
+
+    loop {
+        match stdin.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(buffer)
+}
+
+
+// Splits the input into records. Records may contain newlines, so only NUL separates them
+// (as with find -print0). Empty records, such as after a trailing NUL, are left out.
+fn split_records(data: &[u8]) -> Vec<&[u8]> {
+    data.split(|&b| b == 0).filter(|record| !record.is_empty()).collect()
+}
+
+
+// Parses a physical length like 30mm, 3cm or 1.2in and returns it in inches.
+fn parse_physical_size(value: &str) -> Result<f64, String> {
+    let value = value.trim().to_lowercase();
+    let (number, inches_per_unit) = if let Some(n) = value.strip_suffix("mm") {
+        (n, 1.0 / 25.4)
+    } else if let Some(n) = value.strip_suffix("cm") {
+        (n, 1.0 / 2.54)
+    } else if let Some(n) = value.strip_suffix("in") {
+        (n, 1.0)
+    } else {
+        return Err("Use a size with a unit: mm, cm or in".to_string());
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok(n * inches_per_unit),
+        _ => Err(format!("Invalid size: {}", number)),
+    }
+}
+
+
+// Parses a share like 20% or 20 and returns it as a fraction.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let number: &str = value.trim().strip_suffix('%').unwrap_or(value.trim());
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n < 100.0 => Ok(n / 100.0),
+        _ => Err(format!("Invalid percentage: {}. Use a number between 0 and 100, e.g. 20%", value)),
+    }
+}
+
+
+// Computes the module scale that makes the printed code (border included) the requested size.
+// SVG can use the exact fractional scale; raster outputs round down to whole pixels.
+// PDF and EPS measure in points, so their scale doesn't depend on the dpi. SVG gets its printed
+// size as width and height, so without a dpi it keeps --scale for its own units.
+fn physical_scale(qr: &impl Grid, args: &Cli, inches: f64) -> f64 {
+    let modules: f64 = edge_modules(qr, args);
+    match (&args.output_type, args.dpi) {
+        (OutputType::SVG | OutputType::SVGDATAURI, Some(dpi)) => return inches * dpi / modules,
+        (OutputType::SVG | OutputType::SVGDATAURI | OutputType::DXF, _) => return args.scale,
+        (OutputType::PDF | OutputType::EPS, _) => return inches * pdf::POINTS_PER_INCH / modules,
+        _ => {}
+    }
+
+    let Some(dpi) = args.dpi else { unreachable!("raster output with a printed size is checked to have a dpi") };
+    let pixels: f64 = inches * dpi;
+    let scale: f64 = (pixels / modules).floor();
+    if scale < 1.0 {
+        eprintln!("A {:.1}mm code needs at least {} pixels at {} dpi. Increase --dpi or the size.", inches * 25.4, modules, dpi);
+        std::process::exit(1);
+    }
+    let actual: f64 = scale * modules;
+    if args.verbose || actual != pixels {
+        eprintln!("Requested {:.1}px ({:.2}mm) at {} dpi; using scale {} for {}px ({:.2}mm).",
+            pixels, inches * 25.4, dpi, scale, actual, actual / dpi * 25.4);
+    }
+    scale
+}
+
+
+// Returns the printed size of the code including border in inches, from --physical or --module-size.
+fn printed_size(qr: &impl Grid, args: &Cli) -> Option<f64> {
+    let modules: f64 = edge_modules(qr, args);
+    args.physical.or(args.module_size.map(|module| module * modules))
+}
+
+
+// Returns the modules along the longer edge of the code, border included, which
+// --physical, --module-size and --size measure. Only rMQR codes are wider than tall.
+fn edge_modules(qr: &impl Grid, args: &Cli) -> f64 {
+    qr.width().max(qr.height()) as f64 + 2.0 * args.border_width
+}
+
+
+// Computes the module scale that fits the code (border included) into `pixels` for --size.
+// SVG, PDF and EPS scale exactly; raster outputs round down to whole pixels per module.
+fn pixel_scale(qr: &impl Grid, args: &Cli, pixels: u32) -> f64 {
+    let modules: f64 = edge_modules(qr, args);
+    if matches!(args.output_type, OutputType::SVG | OutputType::SVGDATAURI | OutputType::PDF | OutputType::EPS) {
+        return pixels as f64 / modules;
+    }
+
+    let scale: f64 = (pixels as f64 / modules).floor();
+    if scale < 1.0 {
+        eprintln!("The code needs at least {} pixels with its border. Increase --size.", modules);
+        std::process::exit(1);
+    }
+    if args.verbose {
+        eprintln!("Using scale {} for {}px; {}px of padding go into the border of PNG output.",
+            scale, scale * modules, pixels as f64 - scale * modules);
+    }
+    scale
+}
+
+
+// Parses a single error correction level, a comma separated list of levels, or "all".
+fn parse_ecc_levels(value: &str) -> Option<Vec<QrCodeEcc>> {
+    if value.eq_ignore_ascii_case("all") {
+        return Some(vec![QrCodeEcc::Low, QrCodeEcc::Medium, QrCodeEcc::Quartile, QrCodeEcc::High]);
+    }
+    value.split(',')
+        .map(|level| match level.trim().to_lowercase().as_str() {
+            "l" => Some(QrCodeEcc::Low),
+            "m" => Some(QrCodeEcc::Medium),
+            "q" => Some(QrCodeEcc::Quartile),
+            "h" => Some(QrCodeEcc::High),
+            _ => None,
+        })
+        .collect()
+}
+
+
+// Inserts a suffix before the file extension, e.g. qrcode.png -> qrcode-L.png.
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name: String = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(file_name)
+}
+
+
+// Raster outputs work in whole pixels, so border and scale must be integers there.
+fn raster_size(border: f64, scale: f64) -> (i32, i32) {
+    if border.fract() != 0.0 || scale.fract() != 0.0 {
+        eprintln!("Border width and scale must be whole numbers for raster output.");
+        std::process::exit(1);
+    }
+    (border as i32, scale as i32)
+}
+
+
+// Refuses to replace an existing file unless overwriting was forced.
+fn check_overwrite(file_path: &Path, force: bool) -> Result<(), String> {
+    if !force && file_path.exists() {
+        return Err(format!("Skipped '{}': file already exists. Use --force to overwrite it.", file_path.display()));
+    }
+    Ok(())
+}
+
+
+// Decides where an output file goes when the path is already taken. A single output refuses
+// to overwrite without --force; batch outputs follow --on-collision instead of stopping.
+// Returns None when the file should be skipped.
+fn resolve_collision(file_path: &Path, args: &Cli, batch: bool) -> Option<PathBuf> {
+    if !writes_file(args) || args.force || !file_path.exists() {
+        return Some(file_path.to_path_buf());
+    }
+
+    if !batch && let Err(e) = check_overwrite(file_path, args.force) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    trace::debug!(file = %file_path.display(), strategy = ?args.on_collision, "output file exists");
+    match args.on_collision {
+        Collision::Overwrite => Some(file_path.to_path_buf()),
+        Collision::Skip => {
+            eprintln!("Skipped '{}': file already exists.", file_path.display());
+            None
+        }
+        Collision::Suffix => {
+            let free: PathBuf = (1..)
+                .map(|n| suffixed_path(file_path, &n.to_string()))
+                .find(|candidate| !candidate.exists())
+                .expect("some numbered file name is free");
+            if args.verbose {
+                eprintln!("'{}' already exists, writing '{}' instead.", file_path.display(), free.display());
+            }
+            Some(free)
+        }
+    }
+}
+
+
+// Returns a string of SVG code for an image depicting
+// the given QR Code, with the given number of border modules.
+// Border and scale may be fractional to hit exact physical dimensions.
+// The string always uses Unix newlines (\n), regardless of the platform.
+fn to_svg_string(qr: &impl Grid, border: f64, scale: f64, style: &SvgStyle) -> String {
+    assert!(border >= 0.0, "Border must be non-negative");
+    assert!(scale > 0.0, "Scale must be positive");
+    assert!(style.bg_radius >= 0.0 && style.bg_bleed >= 0.0, "Background radius and bleed must be non-negative");
+    let mut result = String::new();
+    result += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    result += "<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">\n";
+    let (code_width, code_height) = ((qr.width() as f64 + border * 2.0) * scale, (qr.height() as f64 + border * 2.0) * scale);
+    let band: f64 = style.caption.as_ref().map_or(0.0, |caption| caption.band_height(qr.width() as f64 * scale, scale));
+    // A frame lies around the code, so the viewBox starts left of and above it
+    let (left, top, right, bottom) = style.frame.as_ref().map_or((0.0, 0.0, 0.0, 0.0), |frame| frame.margins(scale));
+    // The bleed lies outside the viewBox, so it must not be clipped away
+    let overflow: &str = if style.bg_bleed > 0.0 { " overflow=\"visible\"" } else { "" };
+    // SVG 1.1 links images through the XLink namespace
+    let xlink: &str = if style.logo.is_some() { " xmlns:xlink=\"http://www.w3.org/1999/xlink\"" } else { "" };
+    let (width, height) = (code_width + left + right, code_height + band + top + bottom);
+    // A printed size is given in millimeters, so the code comes out that size wherever it is placed
+    let length = |units: f64| match style.physical {
+        Some(inches) => format!("{}mm", svg_number(units / code_width.max(code_height) * inches * 25.4)),
+        None => svg_number(units),
+    };
+    result += &format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\"{7} version=\"1.1\" viewBox=\"{0} {1} {2} {3}\" width=\"{4}\" height=\"{5}\" stroke=\"none\"{6}>\n",
+        svg_number(-left), svg_number(-top), svg_number(width), svg_number(height), length(width), length(height), overflow, xlink);
+    let light: String = style.bg.unwrap_or(Paint::Hex(Color::WHITE)).svg();
+    let dark: String = style.fg.unwrap_or(Paint::Hex(Color::BLACK)).svg();
+    if style.bg == Some(Paint::None) {
+        // No background rectangle at all, so whatever is behind the image shows through
+    } else if style.bg_radius == 0.0 && style.bg_bleed == 0.0 && style.frame.is_none() {
+        result += &format!("\t<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n", light);
+    } else {
+        let bleed: f64 = style.bg_bleed * scale;
+        result += &format!("\t<rect x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{2}\" rx=\"{3}\" fill=\"{4}\"/>\n",
+            svg_number(-bleed), svg_number(code_width + bleed * 2.0), svg_number(code_height + band + bleed * 2.0), svg_number(style.bg_radius * scale), light);
+    }
+    if let Some(frame) = &style.frame {
+        let fill: String = frame.color.map_or_else(|| dark.clone(), |color| Paint::Hex(color).svg());
+        // The label is cut out of the ribbon in the light color, or white where there is none
+        let text_fill: String = style.bg.and_then(Paint::color).map_or_else(|| Paint::Hex(Color::WHITE).svg(), |color| Paint::Hex(color).svg());
+        result += &frame.svg(code_width, code_height + band, scale, &fill, &text_fill);
+    }
+    // Each shape is kept with its top-left module, which tells finder patterns apart
+    let mut shapes: Vec<((i32, i32), String)> = match &style.modules.renderer {
+        // Runs of dark modules are merged into rectangles, which keeps large versions small
+        None => dark_rects(qr)
+            .into_iter()
+            .map(|(x, y, width, height)| {
+                let width = svg_number(width as f64 * scale);
+                ((x, y), format!("M{},{}h{}v{}h-{}z", svg_number((x as f64 + border) * scale), svg_number((y as f64 + border) * scale),
+                    width, svg_number(height as f64 * scale), width))
+            })
+            .collect(),
+        Some(renderer) => (0..qr.height())
+            .flat_map(|y| (0..qr.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| qr.get_module(x, y))
+            .map(|(x, y)| {
+                let (left, top) = ((x as f64 + border) * scale, (y as f64 + border) * scale);
+                let path: String = if shape::is_shaped(qr, x, y) {
+                    renderer.svg_path(qr, x, y, left, top, scale)
+                } else {
+                    format!("M{},{}h{2}v{2}h-{2}z", svg_number(left), svg_number(top), svg_number(scale))
+                };
+                ((x, y), path)
+            })
+            .collect(),
+    };
+    // Styled eyes are drawn whole, in place of their modules
+    let eyes: Option<shape::Eyes> = style.modules.eyes;
+    if eyes.is_some() {
+        shapes.retain(|&((x, y), _)| qr.finder_at(x, y).is_none());
+    }
+
+    // The modules under the logo are masked out rather than left out, so every shape stays intact
+    if let Some((_, size)) = &style.logo {
+        let (start, end) = logo_area(qr, *size);
+        let (cleared, origin) = ((end - start) as f64 * scale, (start as f64 + border) * scale);
+        result += &format!("\t<mask id=\"logo-area\"><rect width=\"100%\" height=\"100%\" fill=\"#FFFFFF\"/><rect x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{1}\" fill=\"#000000\"/></mask>\n",
+            svg_number(origin), svg_number(cleared));
+    }
+    let mask: &str = if style.logo.is_some() { " mask=\"url(#logo-area)\"" } else { "" };
+    let path = |shapes: &[((i32, i32), String)], fill: &str| -> String {
+        let d: Vec<&str> = shapes.iter().map(|(_, d)| d.as_str()).collect();
+        format!("\t<path d=\"{}\" fill=\"{}\"{}/>\n", d.join(" "), fill, mask)
+    };
+    match &style.gradient {
+        Some(gradient) => {
+            let side: f64 = qr.width() as f64 * scale;
+            result += &format!("\t{}\n", gradient.svg("dark-gradient", border * scale, border * scale, side));
+            // Finder patterns stay solid, so scanners still find the code
+            let (finders, rest): (Vec<_>, Vec<_>) = shapes.into_iter().partition(|&((x, y), _)| qr.finder_at(x, y).is_some());
+            if !finders.is_empty() {
+                result += &path(&finders, &dark);
+            }
+            result += &path(&rest, "url(#dark-gradient)");
+        }
+        None => result += &path(&shapes, &dark),
+    }
+    if let Some(eyes) = eyes {
+        // Eyes of the same color share a path
+        let mut fills: Vec<(String, Vec<String>)> = Vec::new();
+        for ((x, y), color) in qr.finder_positions().into_iter().zip(eyes.colors) {
+            let fill: String = color.map_or_else(|| dark.clone(), |color| Paint::Hex(color).svg());
+            let d: String = eyes.svg_path((x as f64 + border) * scale, (y as f64 + border) * scale, scale);
+            match fills.iter_mut().find(|(existing, _)| *existing == fill) {
+                Some((_, paths)) => paths.push(d),
+                None => fills.push((fill, vec![d])),
+            }
+        }
+        for (fill, d) in fills {
+            result += &format!("\t<path d=\"{}\" fill=\"{}\" fill-rule=\"evenodd\"{}/>\n", d.join(" "), fill, mask);
+        }
+    }
+
+    if let Some((href, size)) = &style.logo {
+        let side: f64 = qr.width() as f64 * size;
+        result += &format!("\t<image x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{1}\" xlink:href=\"{2}\"/>\n",
+            svg_number(((qr.width() as f64 - side) / 2.0 + border) * scale), svg_number(side * scale), href);
+    }
+    if let Some(caption) = &style.caption {
+        result += &format!("\t<path d=\"{}\" fill=\"{}\"/>\n", caption.svg_path(border * scale, code_height, qr.width() as f64 * scale, scale), dark);
+    }
+    result += "</svg>\n";
+    result
+}
+
+
+// Returns the QR code as an HTML table with one `scale` pixel cell per module, for email
+// templates and pages where images are blocked. Styles are inline because many mail
+// clients drop style sheets. The payload is kept in a data-payload attribute.
+// Inverting swaps black and white.
+fn to_html_string(qr: &impl Grid, border: i32, scale: i32, payload: &str, fragment: bool, invert: bool) -> String {
+    let (dark, light) = if invert { ("#FFFFFF", "#000000") } else { ("#000000", "#FFFFFF") };
+    let mut table: String = format!(
+        "<table data-payload=\"{}\" cellpadding=\"0\" cellspacing=\"0\" style=\"border-collapse:collapse;border-spacing:0;background:{}\">\n",
+        html_escape(payload), light);
+    for y in -border..qr.height() + border {
+        table += "<tr>";
+        for x in -border..qr.width() + border {
+            let color: &str = if qr.get_module(x, y) { dark } else { light };
+            table += &format!("<td style=\"width:{0}px;height:{0}px;padding:0;background:{1}\"></td>", scale, color);
+        }
+        table += "</tr>\n";
+    }
+    table += "</table>\n";
+
+    if fragment {
+        return table;
+    }
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(payload), table)
+}
+
+
+// Escapes text for use in HTML element content and quoted attribute values.
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+// Returns an Encapsulated PostScript drawing of the QR code, `scale` points per module.
+// Border and scale may be fractional, as with SVG. Inverting swaps black and white.
+fn to_eps_string(qr: &impl Grid, border: f64, scale: f64, invert: bool) -> String {
+    let (width, height) = ((qr.width() as f64 + border * 2.0) * scale, (qr.height() as f64 + border * 2.0) * scale);
+    let mut result = String::new();
+    result += "%!PS-Adobe-3.0 EPSF-3.0\n";
+    result += &format!("%%BoundingBox: 0 0 {} {}\n", width.ceil(), height.ceil());
+    result += &format!("%%HiResBoundingBox: 0 0 {} {}\n", svg_number(width), svg_number(height));
+    result += "%%Creator: qr-gen\n%%Pages: 1\n%%EndComments\n";
+    let (dark, light) = if invert { (1, 0) } else { (0, 1) };
+    result += &format!("{2} setgray 0 0 {0} {1} rectfill\n{3} setgray\n", svg_number(width), svg_number(height), light, dark);
+    // PostScript measures y upwards from the bottom
+    for (x, y, length) in dark_runs(qr) {
+        result += &format!("{} {} {} {} rectfill\n",
+            svg_number((x as f64 + border) * scale), svg_number(height - (y as f64 + border + 1.0) * scale),
+            svg_number(length as f64 * scale), svg_number(scale));
+    }
+    result += "showpage\n%%EOF\n";
+    result
+}
+
+
+// Returns a DXF drawing for laser cutters and engravers, in millimeters, with every merged
+// rectangle of dark modules as a closed polyline. The quiet zone is kept as an offset from
+// the origin. R12 entities are used since every CAM program reads them.
+fn to_dxf_string(qr: &impl Grid, border: f64, module_mm: f64) -> String {
+    let drawing_height: f64 = (qr.height() as f64 + border * 2.0) * module_mm;
+    // Group codes and values alternate line by line; $INSUNITS 4 means millimeters
+    let mut result: String = "0\nSECTION\n2\nHEADER\n9\n$INSUNITS\n70\n4\n0\nENDSEC\n0\nSECTION\n2\nENTITIES\n".to_string();
+    for (x, y, width, height) in dark_rects(qr) {
+        let left: f64 = (x as f64 + border) * module_mm;
+        let right: f64 = left + width as f64 * module_mm;
+        // DXF measures y upwards from the bottom
+        let top: f64 = drawing_height - (y as f64 + border) * module_mm;
+        let bottom: f64 = top - height as f64 * module_mm;
+        result += "0\nPOLYLINE\n8\nQR\n66\n1\n10\n0\n20\n0\n30\n0\n70\n1\n";
+        for (vx, vy) in [(left, bottom), (right, bottom), (right, top), (left, top)] {
+            result += &format!("0\nVERTEX\n8\nQR\n10\n{}\n20\n{}\n", svg_number(vx), svg_number(vy));
+        }
+        result += "0\nSEQEND\n8\nQR\n";
+    }
+    result += "0\nENDSEC\n0\nEOF\n";
+    result
+}
+
+
+// A symbol of dark and light modules, `width` by `height`, which is all the renderers need,
+// so they draw Micro QR and the rectangular rMQR codes as well as QR codes.
+trait Grid {
+    fn width(&self) -> i32;
+    fn height(&self) -> i32;
+    fn get_module(&self, x: i32, y: i32) -> bool;
+
+    // Returns whether the module at (x, y) belongs to a function pattern or the format and
+    // version information, which shapes leave as solid squares.
+    fn is_function_module(&self, x: i32, y: i32) -> bool;
+
+    // Returns the top-left modules of the 7x7 finder patterns, the eyes of the code.
+    fn finder_positions(&self) -> Vec<(i32, i32)>;
+
+    // Returns the version as people write it, e.g. 7, M3 or R7x43.
+    fn version_name(&self) -> String;
+
+    fn error_correction_level(&self) -> QrCodeEcc;
+
+    // Returns the width in modules of the light margin that scanners need around the symbol.
+    fn quiet_zone(&self) -> i32;
+
+    // Returns the QR code itself, for the outputs that only exist for QR codes.
+    fn qr_code(&self) -> Option<&QrCode> {
+        None
+    }
+
+    // Returns the top-left module of the finder pattern that the module at (x, y) belongs to.
+    fn finder_at(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        self.finder_positions().into_iter().find(|&(left, top)| (left..left + 7).contains(&x) && (top..top + 7).contains(&y))
+    }
+}
+
+impl Grid for QrCode {
+    fn width(&self) -> i32 {
+        self.size()
+    }
+
+    fn height(&self) -> i32 {
+        self.size()
+    }
+
+    fn get_module(&self, x: i32, y: i32) -> bool {
+        QrCode::get_module(self, x, y)
+    }
+
+    fn is_function_module(&self, x: i32, y: i32) -> bool {
+        spec::is_functional_module(x, y, self.version().value())
+    }
+
+    fn finder_positions(&self) -> Vec<(i32, i32)> {
+        spec::finder_positions(self.version().value()).to_vec()
+    }
+
+    fn version_name(&self) -> String {
+        self.version().value().to_string()
+    }
+
+    fn error_correction_level(&self) -> QrCodeEcc {
+        QrCode::error_correction_level(self)
+    }
+
+    fn quiet_zone(&self) -> i32 {
+        QUIET_ZONE
+    }
+
+    fn qr_code(&self) -> Option<&QrCode> {
+        Some(self)
+    }
+
+    fn finder_at(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        spec::finder_at(self.version().value(), x, y)
+    }
+}
+
+
+// Returns the horizontal runs of dark modules as (x, y, length), row by row,
+// so vector outputs can draw each run as a single rectangle.
+fn dark_runs(qr: &impl Grid) -> Vec<(i32, i32, i32)> {
+    let mut runs: Vec<(i32, i32, i32)> = Vec::new();
+    for y in 0..qr.height() {
+        let mut x: i32 = 0;
+        while x < qr.width() {
+            let start: i32 = x;
+            while x < qr.width() && qr.get_module(x, y) {
+                x += 1;
+            }
+            if x > start {
+                runs.push((start, y, x - start));
+            } else {
+                x += 1;
+            }
+        }
+    }
+    runs
+}
+
+
+// Returns the module grid, without quiet zone, as JSON for custom renderers:
+// {"size":21,"version":1,"modules":[[1,1,...],...]} with rows first and 1 for dark.
+fn to_matrix_json(qr: &QrCode) -> String {
+    #[derive(serde::Serialize)]
+    struct Matrix {
+        size: i32,
+        version: u8,
+        modules: Vec<Vec<u8>>,
+    }
+
+    let modules: Vec<Vec<u8>> = (0..qr.size()).map(|y| (0..qr.size()).map(|x| u8::from(qr.get_module(x, y))).collect()).collect();
+    serde_json::to_string(&Matrix { size: qr.size(), version: qr.version().value(), modules }).unwrap_or_default()
+}
+
+
+// Returns the dark modules as (x, y, width, height) rectangles: the horizontal runs,
+// each stacked with identical runs directly below it, as in the finder patterns.
+fn dark_rects(qr: &impl Grid) -> Vec<(i32, i32, i32, i32)> {
+    let mut rects: Vec<(i32, i32, i32, i32)> = Vec::new();
+    // Index of the rectangle last extended for each (x, length) of run
+    let mut stacks: HashMap<(i32, i32), usize> = HashMap::new();
+    for (x, y, length) in dark_runs(qr) {
+        match stacks.get(&(x, length)) {
+            Some(&i) if rects[i].1 + rects[i].3 == y => rects[i].3 += 1,
+            _ => {
+                stacks.insert((x, length), rects.len());
+                rects.push((x, y, length, 1));
+            }
+        }
+    }
+    rects
+}
+
+
+// SVG specific styling. The default draws square black modules on a plain white rectangle
+// covering exactly the image; the background radius and bleed are in modules.
+#[derive(Default)]
+struct SvgStyle {
+    fg: Option<Paint>,
+    bg: Option<Paint>,
+    bg_radius: f64,
+    bg_bleed: f64,
+    modules: ModuleStyle,
+    gradient: Option<Gradient>,
+    // Link or data URI of the logo, and its width as a share of the symbol's
+    logo: Option<(String, f64)>,
+    caption: Option<Caption>,
+    frame: Option<Frame>,
+    // Printed size of the code including border in inches
+    physical: Option<f64>,
+}
+
+impl SvgStyle {
+    fn from_args(args: &Cli) -> Self {
+        // A transparent code is one without a background rectangle
+        let (fg, bg) = paints(args);
+        let bg: Option<Paint> = if args.transparent { Some(Paint::None) } else { bg };
+        let logo: Option<(String, f64)> = args.logo.as_deref().map(|path| (svg_logo_href(path, args.logo_link), args.logo_size));
+        SvgStyle { fg, bg, bg_radius: args.bg_radius, bg_bleed: args.bg_bleed, modules: ModuleStyle::from_args(args), gradient: args.gradient, logo,
+            caption: Caption::from_args(args), frame: Frame::from_args(args), physical: None }
+    }
+}
+
+
+// Returns the logo reference for SVG output: the path as given, or the file as a data URI.
+fn svg_logo_href(path: &Path, link: bool) -> String {
+    if link {
+        return html_escape(&path.to_string_lossy());
+    }
+    let bytes: Vec<u8> = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read logo '{}': {}", path.display(), e);
+        std::process::exit(1);
+    });
+    let media_type: &str = match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("svg") => "image/svg+xml",
+        _ => image::ImageFormat::from_path(path).map_or("application/octet-stream", |format| format.to_mime_type()),
+    };
+    to_data_uri(media_type, &bytes)
+}
+
+
+// Returns the range of modules, in both directions, that a centered logo `size` times
+// the width of the symbol hides: every module it touches plus a margin of one module.
+// Only square QR codes take logos.
+fn logo_area(qr: &impl Grid, size: f64) -> (i32, i32) {
+    let side: f64 = qr.width() as f64 * size;
+    let start: i32 = (((qr.width() as f64 - side) / 2.0).floor() as i32 - 1).max(0);
+    (start, qr.width() - start)
+}
+
+
+// Warns, or under --strict aborts, when a logo over the given modules hides more codewords
+// of some error correction block than the block can correct.
+fn check_logo_area(qr: &QrCode, (start, end): (i32, i32), strict: bool) {
+    let version: u8 = qr.version().value();
+    let ecl: QrCodeEcc = qr.error_correction_level();
+    let blocks: Vec<usize> = spec::interleaved_blocks(version, ecl);
+    let mut hidden: Vec<bool> = vec![false; blocks.len()];
+    for (i, (x, y)) in spec::placement_order(version).into_iter().enumerate() {
+        if i / 8 < hidden.len() && (start..end).contains(&x) && (start..end).contains(&y) {
+            hidden[i / 8] = true;
+        }
+    }
+
+    let mut per_block: Vec<usize> = vec![0; spec::num_ec_blocks(version, ecl)];
+    for (&block, _) in blocks.iter().zip(&hidden).filter(|(_, hidden)| **hidden) {
+        per_block[block] += 1;
+    }
+    let worst: usize = per_block.into_iter().max().unwrap_or(0);
+    let correctable: usize = spec::correctable_codewords(version, ecl);
+    if worst > correctable {
+        let message: String = format!("the logo hides {} codewords of an error correction block, but ECC {} only restores {}. Use a smaller --logo-size or a higher --ecc.",
+            worst, spec::ecc_letter(ecl), correctable);
+        if strict {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+        eprintln!("Warning: {}", message);
+    }
+}
+
+
+// Formats an SVG coordinate, rounded to 4 decimals so float noise doesn't leak into the output.
+// Adding zero turns a negative zero into a plain one.
+fn svg_number(value: f64) -> String {
+    format!("{}", (value * 10000.0).round() / 10000.0 + 0.0)
+}
+
+
+// Prints the mode and size of every segment to stderr.
+// Header bits depend on the version, so they are shown for the one the code was encoded at.
+fn print_segments(segs: &[QrSegment], version: u8) {
+    for (i, seg) in segs.iter().enumerate() {
+        eprintln!("Segment {}: {:?}, {} chars, {} data bits + {} header bits at version {}",
+            i + 1, seg.mode(), seg.num_chars(), seg.data().len(), 4 + spec::char_count_bits(seg.mode(), version), version);
+    }
+}
+
+
+// Prints the codeword layout of the given QrCode object to stderr.
+fn print_stats(qr: &QrCode) {
+    let version: u8 = qr.version().value();
+    let ecl: QrCodeEcc = qr.error_correction_level();
+    eprintln!("{:>7}  {:>3}  {:>14}  {:>12}  {:>9}  {:>14}", "version", "ecc", "data codewords", "ec codewords", "ec blocks", "remainder bits");
+    eprintln!("{:>7}  {:>3}  {:>14}  {:>12}  {:>9}  {:>14}",
+        version,
+        spec::ecc_letter(ecl),
+        spec::num_data_codewords(version, ecl),
+        spec::num_ec_codewords(version, ecl),
+        spec::num_ec_blocks(version, ecl),
+        spec::num_remainder_bits(version));
+}
+
+
+// Returns a headerless 1-bit monochrome bitmap of the given QrCode object,
+// as expected by many e-paper and LCD drivers. Dark modules are 1 bits,
+// packed MSB-first, and every row is padded to a whole number of bytes.
+// Inverting makes the light modules and the border the 1 bits instead.
+fn to_raw1_bitmap(qr: &impl Grid, border: i32, scale: i32, invert: bool) -> Vec<u8> {
+    to_shaped_bitmap(qr, border, scale, &ModuleStyle::default(), invert)
+}
+
+
+// Returns the 1-bit bitmap of to_raw1_bitmap with the modules drawn in the given style.
+fn to_shaped_bitmap(qr: &impl Grid, border: i32, scale: i32, modules: &ModuleStyle, invert: bool) -> Vec<u8> {
+    assert!(border >= 0, "Border must be non-negative");
+    assert!(scale > 0, "Scale must be positive");
+    let width: usize = ((qr.width() + 2 * border) * scale) as usize;
+    let height: usize = ((qr.height() + 2 * border) * scale) as usize;
+    let stride: usize = width.div_ceil(8);
+    let mut bitmap: Vec<u8> = vec![0u8; stride * height];
+    for py in 0..height {
+        for px in 0..width {
+            if shape::is_dark_pixel(qr, modules, border, scale as u32, px as u32, py as u32) != invert {
+                bitmap[py * stride + px / 8] |= 0x80 >> (px % 8);
+            }
+        }
+    }
+    bitmap
+}
+
+
+// Returns the given QrCode object as a binary PBM (P4) image: the raw 1-bit bitmap
+// behind a Netpbm header, so every module maps to exactly scale x scale pixels.
+fn to_pbm_bytes(qr: &impl Grid, border: i32, scale: i32, invert: bool) -> Vec<u8> {
+    let (width, height) = ((qr.width() + 2 * border) * scale, (qr.height() + 2 * border) * scale);
+    let mut bytes: Vec<u8> = format!("P4\n{} {}\n", width, height).into_bytes();
+    bytes.extend(to_raw1_bitmap(qr, border, scale, invert));
+    bytes
+}
+
+
+// Returns a grayscale image as a binary PGM (P5) image with 8-bit samples.
+fn to_pgm_bytes(image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<u8> {
+    let mut bytes: Vec<u8> = format!("P5\n{} {}\n255\n", image.width(), image.height()).into_bytes();
+    bytes.extend_from_slice(image.as_raw());
+    bytes
+}
+
+
+// Returns the given QrCode object as an X BitMap, which is C source itself:
+// 1 bits are dark, packed LSB-first and every row padded to whole bytes.
+fn to_xbm_string(qr: &impl Grid, border: i32, scale: i32, name: &str, invert: bool) -> String {
+    let (width, height) = ((qr.width() + 2 * border) * scale, (qr.height() + 2 * border) * scale);
+    let bits: Vec<u8> = to_raw1_bitmap(qr, border, scale, invert).iter().map(|byte| byte.reverse_bits()).collect();
+    format!("#define {name}_width {width}\n#define {name}_height {height}\nstatic unsigned char {name}_bits[] = {{\n{}}};\n",
+        c_byte_rows(&bits))
+}
+
+
+// Returns the given QrCode object as a C byte array ready to be compiled into firmware.
+// The layout is that of the RAW1 output: 1 bits are dark, packed MSB-first, rows padded to whole bytes.
+fn to_c_array_string(qr: &impl Grid, border: i32, scale: i32, name: &str, invert: bool) -> String {
+    let (width, height) = ((qr.width() + 2 * border) * scale, (qr.height() + 2 * border) * scale);
+    let upper: String = name.to_uppercase();
+    format!("// {width}x{height} QR code, 1 bit per pixel, MSB first, 1 = dark, rows padded to whole bytes\n\
+        #define {upper}_WIDTH {width}\n#define {upper}_HEIGHT {height}\n\
+        static const unsigned char {name}_bitmap[] = {{\n{}}};\n",
+        c_byte_rows(&to_raw1_bitmap(qr, border, scale, invert)))
+}
+
+
+// Returns the module matrix, without quiet zone, as a constant array in Rust or C source,
+// so firmware can draw the code itself. Rows come first and dark modules are true or 1.
+fn to_matrix_source(qr: &QrCode, language: Language, name: &str) -> String {
+    let upper: String = name.to_uppercase();
+    let size: i32 = qr.size();
+    let header: String = format!("{}x{} QR code, version {}, row-major, dark modules are {}",
+        size, size, qr.version().value(), if matches!(language, Language::Rust) { "true" } else { "1" });
+    let rows = (0..size).map(|y| {
+        let modules = (0..size).map(|x| match language {
+            Language::Rust => if qr.get_module(x, y) { "true" } else { "false" },
+            Language::C => if qr.get_module(x, y) { "1" } else { "0" },
+        });
+        modules.collect::<Vec<&str>>().join(", ")
+    });
+
+    match language {
+        Language::Rust => format!("// {header}\npub const {upper}_SIZE: usize = {size};\n\
+            pub const {upper}_MODULES: [[bool; {upper}_SIZE]; {upper}_SIZE] = [\n{}];\n",
+            rows.map(|row| format!("    [{}],\n", row)).collect::<String>()),
+        Language::C => format!("// {header}\n#define {upper}_SIZE {size}\n\
+            static const unsigned char {name}_modules[{upper}_SIZE][{upper}_SIZE] = {{\n{}}};\n",
+            rows.map(|row| format!("    {{{}}},\n", row)).collect::<String>()),
+    }
+}
+
+
+// Formats bytes as indented lines of comma separated hex literals for a C initializer.
+fn c_byte_rows(bytes: &[u8]) -> String {
+    bytes
+        .chunks(12)
+        .map(|row| format!("    {},\n", row.iter().map(|byte| format!("0x{:02x}", byte)).collect::<Vec<String>>().join(", ")))
+        .collect()
+}
+
+
+// Turns the output file stem into a C identifier for the generated symbols, e.g. my-code.h -> my_code.
+fn c_identifier(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name: String = stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    match name.chars().next() {
+        None => "qrcode".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("_{}", name),
+        Some(_) => name,
+    }
+}
+
+
+// Prints a table of the maximum payload size per version and error correction level,
+// for every encoding mode or just the given one.
+fn print_capacities(version: Option<u8>, mode: Option<QrSegmentMode>) {
+    let modes: Vec<(QrSegmentMode, &str)> = [
+        (QrSegmentMode::Numeric, "numeric"),
+        (QrSegmentMode::Alphanumeric, "alphanumeric"),
+        (QrSegmentMode::Byte, "byte"),
+        (QrSegmentMode::Kanji, "kanji"),
+    ].into_iter().filter(|(m, _)| mode.is_none_or(|wanted| wanted == *m)).collect();
+    let versions: Vec<u8> = match version {
+        Some(version) => vec![version],
+        None => (1..=40).collect(),
+    };
+
+    let mut header: String = format!("{:>7}  {:>3}", "version", "ecc");
+    for (_, name) in &modes {
+        header += &format!("  {:>12}", name);
+    }
+    println!("{}", header);
+
+    for &version in &versions {
+        for ecl in [QrCodeEcc::Low, QrCodeEcc::Medium, QrCodeEcc::Quartile, QrCodeEcc::High] {
+            let mut row: String = format!("{:>7}  {:>3}", version, spec::ecc_letter(ecl));
+            for (m, _) in &modes {
+                row += &format!("  {:>12}", spec::max_chars(*m, version, ecl));
+            }
+            println!("{}", row);
+        }
+    }
+}
+
+
+// Prints the module grid to stderr, one character per module, labeled by what the module is for.
+// Function patterns use letters, uppercase when dark and lowercase when light; data modules use # and .
+fn print_matrix(qr: &QrCode) {
+    let version: u8 = qr.version().value();
+    let data_modules: usize = (0..qr.size())
+        .flat_map(|y| (0..qr.size()).map(move |x| (x, y)))
+        .filter(|&(x, y)| !spec::is_functional_module(x, y, version))
+        .count();
+    eprintln!("Version {}, {}x{} modules, {} of them for data", version, qr.size(), qr.size(), data_modules);
+    eprintln!("F finder  S separator  T timing  A alignment  I format info  V version info  K dark module  #/. data");
+    for y in 0..qr.size() {
+        let row: String = (0..qr.size()).map(|x| {
+            let label: char = match spec::module_kind(version, x, y) {
+                spec::ModuleKind::Finder => 'F',
+                spec::ModuleKind::Separator => 'S',
+                spec::ModuleKind::Timing => 'T',
+                spec::ModuleKind::Alignment => 'A',
+                spec::ModuleKind::Format => 'I',
+                spec::ModuleKind::Version => 'V',
+                spec::ModuleKind::DarkModule => 'K',
+                spec::ModuleKind::Data => return if qr.get_module(x, y) { '#' } else { '.' },
+            };
+            if qr.get_module(x, y) { label } else { label.to_ascii_lowercase() }
+        }).collect();
+        eprintln!("{}", row);
+    }
+}
+
+
+// Re-encodes the code with each of the 8 mask patterns and prints their
+// penalty scores to stderr. The library picks the mask with the lowest total.
+fn print_mask_penalties(qr: &QrCode, segs: &[QrSegment]) {
+    let version = qr.version();
+    let ecl: QrCodeEcc = qr.error_correction_level();
+    eprintln!("{:>4}  {:>5}  {:>6}  {:>7}  {:>7}  {:>5}", "mask", "runs", "blocks", "finders", "balance", "total");
+    for mask in 0..8 {
+        let masked: QrCode = QrCode::encode_segments_advanced(segs, ecl, version, version, Some(Mask::new(mask)), false)
+            .expect("the data fits the version it was encoded at");
+        let [runs, blocks, finders, balance] = penalty::penalty_scores(&masked);
+        let chosen: &str = if mask == qr.mask().value() { "  <- chosen" } else { "" };
+        eprintln!("{:>4}  {:>5}  {:>6}  {:>7}  {:>7}  {:>5}{}", mask, runs, blocks, finders, balance, runs + blocks + finders + balance, chosen);
+    }
+}
+
+
+// Returns the given QrCode object drawn with the given glyphs, one line per row of characters.
+// Inverting swaps dark and light for light-on-dark terminals. When the glyphs pack several
+// modules into one character, the last row and column are padded with light modules.
+fn to_text_string(qr: &impl Grid, border: i32, invert: bool, glyphs: Glyphs) -> String {
+    let (cell_width, cell_height) = glyphs.cell_size();
+    let (right, bottom) = (qr.width() + border, qr.height() + border);
+    let mut result = String::new();
+    for y in (-border .. bottom).step_by(cell_height as usize) {
+        for x in (-border .. right).step_by(cell_width as usize) {
+            let mut dark: usize = 0;
+            for dy in 0..cell_height {
+                for dx in 0..cell_width {
+                    let module: bool = x + dx < right && y + dy < bottom && qr.get_module(x + dx, y + dy);
+                    if module != invert {
+                        dark |= 1 << (dy * cell_width + dx);
+                    }
+                }
+            }
+            glyphs.push_cell(&mut result, dark);
+        }
+        result.push('\n');
+    }
+    result
+}
+
+
+// Returns the QrCode object drawn with ANSI background colors, two spaces per module, or with
+// half glyphs one ▀ per two modules, colored top in the foreground and bottom in the background.
+// Without colors the basic black and bright white are used, which every terminal knows.
+fn to_ansi_string(qr: &impl Grid, border: i32, half: bool, colors: Option<(Color, Color)>) -> String {
+    let escape = |dark: bool, background: bool| -> String {
+        match colors {
+            Some((fg, bg)) => {
+                let color: Color = if dark { fg } else { bg };
+                format!("\x1b[{};2;{};{};{}m", if background { 48 } else { 38 }, color.r, color.g, color.b)
+            }
+            None => format!("\x1b[{}m", match (dark, background) {
+                (true, true) => 40,
+                (false, true) => 107,
+                (true, false) => 30,
+                (false, false) => 97,
+            }),
+        }
+    };
+
+    let (right, bottom) = (qr.width() + border, qr.height() + border);
+    let mut result = String::new();
+    for y in (-border..bottom).step_by(if half { 2 } else { 1 }) {
+        // Escapes are only repeated when the color changes along the row
+        let mut current = String::new();
+        for x in -border..right {
+            let cell: String = if half {
+                let bottom: bool = y + 1 < bottom && qr.get_module(x, y + 1);
+                escape(qr.get_module(x, y), false) + &escape(bottom, true)
+            } else {
+                escape(qr.get_module(x, y), true)
+            };
+            if cell != current {
+                result += &cell;
+                current = cell;
+            }
+            result += if half { "▀" } else { "  " };
+        }
+        result += "\x1b[0m\n";
+    }
+    result
+}
+
+
+// Returns the given QrCode object encoded as a PNG image in memory. A two-tone code
+// needs only 1 bit per pixel, which makes files several times smaller than 8-bit gray.
+fn to_png_bytes(qr: &impl Grid, border: i32, scale_factor: u32, info: &PngInfo) -> Result<Vec<u8>, String> {
+    if border < 0 {
+        return Err("Border must be non-negative".to_string());
+    }
+    if scale_factor < 1 {
+        return Err("Scale factor must be positive".to_string());
+    }
+
+    let width: u32 = (qr.width() + 2 * border) as u32 * scale_factor;
+    let height: u32 = (qr.height() + 2 * border) as u32 * scale_factor;
+    let eye_color: bool = info.modules.eyes.is_some_and(|eyes| eyes.colors.iter().any(Option::is_some));
+    if info.transparent || info.gradient.is_some() || eye_color || info.caption.is_some() || info.frame.is_some() || info.blend.is_some() {
+        // 8-bit RGBA, for fully transparent light pixels or more than two colors
+        let image: RgbaImage = to_rgba_image(qr, border, scale_factor, info);
+        let image: RgbaImage = with_frame(with_caption(with_padding(image, info), qr, scale_factor, info), scale_factor, info);
+        let (width, height) = image.dimensions();
+        return encode_png(width, height, png::ColorType::Rgba, png::BitDepth::Eight, &[], image.as_raw(), info);
+    }
+
+    let bitmap: Vec<u8> = to_shaped_bitmap(qr, border, scale_factor as i32, &info.modules, false);
+    let (before, after) = size_padding(width.max(height), info);
+    let bitmap: Vec<u8> = pad_bitmap(&bitmap, width as usize, height as usize, before as usize, after as usize);
+    let (width, height) = (width + before + after, height + before + after);
+    if info.dark == Color::BLACK && info.light == Color::WHITE {
+        // PNG grayscale stores 1 bits for white, the opposite of the RAW1 bitmap
+        let pixels: Vec<u8> = bitmap.iter().map(|byte| !byte).collect();
+        encode_png(width, height, png::ColorType::Grayscale, png::BitDepth::One, &[], &pixels, info)
+    } else {
+        // Other colors make a two-entry palette, which keeps the file just as small as gray
+        let palette: [u8; 6] = [info.light.r, info.light.g, info.light.b, info.dark.r, info.dark.g, info.dark.b];
+        encode_png(width, height, png::ColorType::Indexed, png::BitDepth::One, &palette, &bitmap, info)
+    }
+}
+
+
+// Encodes packed pixel rows as a PNG image with the given extra chunks.
+// The palette, as RGB triples, is only written for indexed color.
+fn encode_png(width: u32, height: u32, color: png::ColorType, depth: png::BitDepth, palette: &[u8], pixels: &[u8], info: &PngInfo) -> Result<Vec<u8>, String> {
+    let error = |e: png::EncodingError| format!("Failed to encode PNG: {}", e);
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    if color == png::ColorType::Indexed {
+        encoder.set_palette(palette.to_vec());
+    }
+    encoder.set_compression(png::Compression::Best);
+    if let Some(dpi) = info.dpi {
+        // pHYs counts pixels per meter
+        let ppm: u32 = (dpi / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: ppm, yppu: ppm, unit: png::Unit::Meter }));
+    }
+    for (keyword, text) in &info.text {
+        // tEXt only holds Latin-1, so anything else goes into a UTF-8 iTXt chunk
+        if text.is_ascii() {
+            encoder.add_text_chunk(keyword.clone(), text.clone()).map_err(error)?;
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), text.clone()).map_err(error)?;
+        }
+    }
+    let mut writer = encoder.write_header().map_err(error)?;
+    writer.write_image_data(pixels).map_err(error)?;
+    writer.finish().map_err(error)?;
+    Ok(bytes)
+}
+
+
+// Colors and extra chunks for PNG files. The default is black on white without extra chunks.
+struct PngInfo {
+    dark: Color,
+    light: Color,
+    // Whether light pixels are left transparent instead of painted in the light color
+    transparent: bool,
+    // Fill of the dark modules other than the finder patterns, which stay in the dark color
+    gradient: Option<Gradient>,
+    modules: ModuleStyle,
+    caption: Option<Caption>,
+    frame: Option<Frame>,
+    blend: Option<Blend>,
+    // Print resolution for the pHYs chunk
+    dpi: Option<f64>,
+    // Edge length in pixels that the code with its border is padded to
+    size: Option<u32>,
+    // Keyword and text pairs
+    text: Vec<(String, String)>,
+}
+
+impl Default for PngInfo {
+    fn default() -> Self {
+        PngInfo { dark: Color::BLACK, light: Color::WHITE, transparent: false, gradient: None, modules: ModuleStyle::default(), caption: None, frame: None, blend: None, dpi: None, size: None, text: Vec::new() }
+    }
+}
+
+impl PngInfo {
+    // Records where a code came from, for asset managers that index PNG metadata.
+    fn describing(qr: &impl Grid, payload: &str) -> Self {
+        let text: Vec<(String, String)> = vec![
+            ("Software".to_string(), format!("qr-gen {}", env!("CARGO_PKG_VERSION"))),
+            ("Creation Time".to_string(), utc_timestamp(std::time::SystemTime::now())),
+            ("QR Payload".to_string(), payload.to_string()),
+            ("QR Error Correction".to_string(), spec::ecc_letter(qr.error_correction_level()).to_string()),
+            ("QR Version".to_string(), qr.version_name()),
+        ];
+        PngInfo { text, ..Default::default() }
+    }
+
+    // Plain black on white, or white on black when inverted, without extra chunks.
+    fn plain(invert: bool) -> Self {
+        if invert { PngInfo { dark: Color::WHITE, light: Color::BLACK, ..Default::default() } } else { PngInfo::default() }
+    }
+}
+
+
+// Formats a time as an ISO 8601 UTC timestamp, e.g. 2024-05-01T12:30:00Z.
+fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let seconds: u64 = time.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = ((seconds / 86400) as i64, seconds % 86400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z: i64 = days + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let day_of_era: i64 = z - era * 146097;
+    let year_of_era: i64 = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month: i64 = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rest / 3600, rest / 60 % 60, rest % 60)
+}
+
+
+// Returns a base64 data URI for inlining an image into HTML, CSS or Markdown.
+fn to_data_uri(media_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", media_type, base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+
+// Returns the given QrCode object encoded as a JPEG image in memory.
+fn to_jpeg_bytes(qr: &impl Grid, border: i32, scale_factor: u32, quality: u8, invert: bool) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality)
+        .encode_image(&to_luma_image(qr, border, scale_factor, invert)?)
+        .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+    Ok(bytes)
+}
+
+
+// Returns the given QrCode object encoded as a lossless WebP image in memory.
+fn to_webp_bytes(qr: &impl Grid, border: i32, scale_factor: u32, invert: bool) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    to_luma_image(qr, border, scale_factor, invert)?
+        .write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(&mut bytes))
+        .map_err(|e| format!("Failed to encode WebP: {}", e))?;
+    Ok(bytes)
+}
+
+
+// Renders the given QrCode object as a grayscale image with the specified scale and border width.
+// Inverting swaps black and white.
+fn to_luma_image(qr: &impl Grid, border: i32, scale_factor: u32, invert: bool) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>, String> {
+    // Validate inputs
+    if border < 0 {
+        return Err("Border must be non-negative".to_string());
+    }
+    if scale_factor < 1 {
+        return Err("Scale factor must be positive".to_string());
+    }
+
+    // Calculate image size
+    let img_width: u32 = (qr.width() + 2 * border) as u32;
+    let img_height: u32 = (qr.height() + 2 * border) as u32;
+    let (dark, light) = if invert { (255u8, 0u8) } else { (0u8, 255u8) };
+    let mut img: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_pixel(img_width, img_height, Luma([light]));
+
+    // Draw QR code onto the image
+    for y in 0..qr.height() {
+        for x in 0..qr.width() {
+            if qr.get_module(x, y) {
+                img.put_pixel((x + border) as u32, (y + border) as u32, Luma([dark]));
+            }
+        }
+    }
+
+    // Scale the image
+    Ok(image::imageops::resize(&img, img_width * scale_factor, img_height * scale_factor, FilterType::Nearest))
+}
+
+
+// Renders the given QrCode object as an RGBA image in the colors of the PNG info,
+// with the light pixels left transparent if it asks for that.
+fn to_rgba_image(qr: &impl Grid, border: i32, scale_factor: u32, info: &PngInfo) -> RgbaImage {
+    let width: u32 = (qr.width() + 2 * border) as u32 * scale_factor;
+    let height: u32 = (qr.height() + 2 * border) as u32 * scale_factor;
+    let light: Rgba<u8> = light_pixel(info);
+    // Position within the symbol, as a share of its width, of the center of a pixel
+    let symbol: f64 = (qr.width() as u32 * scale_factor) as f64;
+    let share = |p: u32| (p as f64 + 0.5 - (border as u32 * scale_factor) as f64) / symbol;
+    ImageBuffer::from_fn(width, height, |px, py| {
+        // A blended picture takes over all but the middle of the data modules
+        if let Some(blend) = &info.blend && let Some(dark) = blend.dark_at(qr, border, scale_factor, px, py) {
+            let Color { r, g, b } = info.dark;
+            return if dark { Rgba([r, g, b, 255]) } else { light };
+        }
+        if !shape::is_dark_pixel(qr, &info.modules, border, scale_factor, px, py) {
+            return light;
+        }
+        let x: i32 = (px / scale_factor) as i32 - border;
+        let y: i32 = (py / scale_factor) as i32 - border;
+        let Color { r, g, b } = match (info.modules.eye_at(qr, x, y), info.gradient) {
+            (Some((eyes, corner)), _) => eyes.color(qr, corner).unwrap_or(info.dark),
+            (None, Some(gradient)) if qr.finder_at(x, y).is_none() => gradient.color_at(share(px), share(py)),
+            _ => info.dark,
+        };
+        Rgba([r, g, b, 255])
+    })
+}
+
+
+// Returns the pixels to add before and after a code `width` pixels wide to reach the size of the PNG info.
+fn size_padding(width: u32, info: &PngInfo) -> (u32, u32) {
+    let extra: u32 = info.size.map_or(0, |size| size.saturating_sub(width));
+    (extra / 2, extra - extra / 2)
+}
+
+
+// Returns the image of the code padded on every side with light pixels to the size of the PNG info.
+// The size is that of the longer side; the other one gets the same padding.
+fn with_padding(image: RgbaImage, info: &PngInfo) -> RgbaImage {
+    let (before, after) = size_padding(image.width().max(image.height()), info);
+    if before + after == 0 {
+        return image;
+    }
+    let mut canvas: RgbaImage = ImageBuffer::from_pixel(image.width() + before + after, image.height() + before + after, light_pixel(info));
+    image::imageops::replace(&mut canvas, &image, before as i64, before as i64);
+    canvas
+}
+
+
+// Returns a 1-bit bitmap, packed as by to_raw1_bitmap, with 0 bits added before and after
+// every row and above and below the rows.
+fn pad_bitmap(bitmap: &[u8], width: usize, height: usize, before: usize, after: usize) -> Vec<u8> {
+    if before + after == 0 {
+        return bitmap.to_vec();
+    }
+    let stride: usize = width.div_ceil(8);
+    let padded_stride: usize = (width + before + after).div_ceil(8);
+    let mut padded: Vec<u8> = vec![0u8; padded_stride * (height + before + after)];
+    for y in 0..height {
+        for x in (0..width).filter(|x| bitmap[y * stride + x / 8] & (0x80 >> (x % 8)) != 0) {
+            let (px, py) = (x + before, y + before);
+            padded[py * padded_stride + px / 8] |= 0x80 >> (px % 8);
+        }
+    }
+    padded
+}
+
+
+// Returns the image of the code extended by a band with the caption of the PNG info, if any.
+// The caption is centered under the symbol, which is centered in the image.
+fn with_caption(image: RgbaImage, qr: &impl Grid, scale_factor: u32, info: &PngInfo) -> RgbaImage {
+    let Some(caption) = &info.caption else { return image };
+    let symbol: f64 = (qr.width() as u32 * scale_factor) as f64;
+    let band: u32 = caption.band_height(symbol, scale_factor as f64).ceil() as u32;
+    let mut canvas: RgbaImage = ImageBuffer::from_pixel(image.width(), image.height() + band, light_pixel(info));
+    image::imageops::replace(&mut canvas, &image, 0, 0);
+    let Color { r, g, b } = info.dark;
+    caption.draw(&mut canvas, (image.width() as f64 - symbol) / 2.0, image.height() as f64, symbol, scale_factor as f64, Rgba([r, g, b, 255]));
+    canvas
+}
+
+
+// Returns the image of the code, with its caption, inside the frame of the PNG info, if any.
+// Outside the frame's rounded corners the image is transparent.
+fn with_frame(image: RgbaImage, scale_factor: u32, info: &PngInfo) -> RgbaImage {
+    let Some(frame) = &info.frame else { return image };
+    let (left, top, right, bottom) = frame.margins(scale_factor as f64);
+    let mut canvas: RgbaImage = ImageBuffer::from_pixel(image.width() + (left + right) as u32, image.height() + (top + bottom) as u32, Rgba([0, 0, 0, 0]));
+    image::imageops::replace(&mut canvas, &image, left as i64, top as i64);
+    let Color { r, g, b } = frame.color.unwrap_or(info.dark);
+    let light: Color = info.light;
+    frame.draw(&mut canvas, image.width() as f64, image.height() as f64, scale_factor as f64, Rgba([r, g, b, 255]), Rgba([light.r, light.g, light.b, 255]));
+    canvas
+}
+
+
+// The pixel PNG output paints light modules with.
+fn light_pixel(info: &PngInfo) -> Rgba<u8> {
+    let Color { r, g, b } = info.light;
+    if info.transparent { Rgba([0, 0, 0, 0]) } else { Rgba([r, g, b, 255]) }
+}
+
+
+// Returns the code as an RGBA PNG with a logo in the center, scaled to `size` times the
+// width of the symbol. The modules of the logo area are cleared, so no cut-off modules
+// show at its edges. Error correction has to make up for them.
+fn to_png_with_logo(qr: &impl Grid, payload: &[u8], border: i32, scale_factor: u32, logo_path: &Path, size: f64, info: &PngInfo) -> Result<Vec<u8>, String> {
+    if border < 0 {
+        return Err("Border must be non-negative".to_string());
+    }
+    if scale_factor < 1 {
+        return Err("Scale factor must be positive".to_string());
+    }
+
+    let mut canvas: RgbaImage = to_rgba_image(qr, border, scale_factor, info);
+    let (start, end) = logo_area(qr, size);
+    let light: Rgba<u8> = light_pixel(info);
+    for y in (start + border) as u32 * scale_factor..(end + border) as u32 * scale_factor {
+        for x in (start + border) as u32 * scale_factor..(end + border) as u32 * scale_factor {
+            canvas.put_pixel(x, y, light);
+        }
+    }
+
+    let side: u32 = ((qr.width() as u32 * scale_factor) as f64 * size).round().max(1.0) as u32;
+    let logo: RgbaImage = image::open(logo_path)
+        .map_err(|e| format!("Failed to open logo '{}': {}", logo_path.display(), e))?
+        .resize(side, side, FilterType::Lanczos3)
+        .into_rgba8();
+
+    let left: u32 = (canvas.width() - logo.width()) / 2;
+    let top: u32 = (canvas.height() - logo.height()) / 2;
+    image::imageops::overlay(&mut canvas, &logo, left as i64, top as i64);
+
+    if !decodes_to(&canvas, payload) {
+        eprintln!("Warning: the QR code could not be decoded with the logo in place. Try a smaller --logo-size.");
+    }
+
+    let canvas: RgbaImage = with_frame(with_caption(with_padding(canvas, info), qr, scale_factor, info), scale_factor, info);
+    let (width, height) = canvas.dimensions();
+    encode_png(width, height, png::ColorType::Rgba, png::BitDepth::Eight, &[], canvas.as_raw(), info)
+}
+
+
+// Returns whether a code in the image decodes to the given payload bytes. Transparent pixels
+// are composited over white first, as on a page, since they would otherwise turn black.
+fn decodes_to(image: &RgbaImage, payload: &[u8]) -> bool {
+    let flat = image::RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, alpha]) = *image.get_pixel(x, y);
+        image::Rgb([r, g, b].map(|c| ((c as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255) as u8))
+    });
+    let gray = image::DynamicImage::ImageRgb8(flat).into_luma8();
+    let mut decoder = quircs::Quirc::default();
+    decoder
+        .identify(gray.width() as usize, gray.height() as usize, &gray)
+        .filter_map(|code| code.ok()?.decode().ok())
+        .any(|data| data.payload == payload)
+}
+
+
+// Returns how many modules of light background surround a symbol, up to the full quiet zone.
+// Dark pixels and the edge of the canvas both end the clear area.
+fn quiet_zone_clearance(canvas: &RgbaImage, symbol_left: i64, symbol_top: i64, (symbol_width, symbol_height): (i64, i64), scale: i64, quiet_zone: i32) -> i32 {
+    for ring in 0..quiet_zone as i64 {
+        let inner: i64 = ring * scale;
+        let outer: i64 = (ring + 1) * scale;
+        for y in (symbol_top - outer)..(symbol_top + symbol_height + outer) {
+            for x in (symbol_left - outer)..(symbol_left + symbol_width + outer) {
+                let inside_ring: bool = x >= symbol_left - inner && x < symbol_left + symbol_width + inner
+                    && y >= symbol_top - inner && y < symbol_top + symbol_height + inner;
+                if inside_ring {
+                    continue;
+                }
+                if x < 0 || y < 0 || x >= canvas.width() as i64 || y >= canvas.height() as i64 {
+                    return ring as i32;
+                }
+                let [r, g, b, _] = canvas.get_pixel(x as u32, y as u32).0;
+                let luma: f64 = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+                if luma < 128.0 {
+                    return ring as i32;
+                }
+            }
+        }
+    }
+    quiet_zone
+}
+
+
+// Reports something that eats into the quiet zone. Under --strict this is fatal.
+fn report_quiet_zone(message: &str, strict: bool) {
+    if strict {
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    }
+    eprintln!("Warning: {}", message);
+}
+
+
+// Composites the given QrCode object, centered, over a background image and returns it as PNG.
+// The background behind the code (quiet zone included) should be light and uniform,
+// so it is checked first and the final image is decoded again to make sure it still scans.
+fn to_png_on_background(qr: &impl Grid, payload: &[u8], border: i32, scale_factor: u32, bg_path: &Path, strict: bool, info: &PngInfo) -> Result<Vec<u8>, String> {
+    if border < 0 {
+        return Err("Border must be non-negative".to_string());
+    }
+    if scale_factor < 1 {
+        return Err("Scale factor must be positive".to_string());
+    }
+
+    // The code is drawn in its styles like any PNG, with its quiet zone cleared to show the background
+    let mut code_img: RgbaImage = to_rgba_image(qr, border, scale_factor, info);
+    let columns: std::ops::Range<u32> = border as u32 * scale_factor..(border + qr.width()) as u32 * scale_factor;
+    let rows: std::ops::Range<u32> = border as u32 * scale_factor..(border + qr.height()) as u32 * scale_factor;
+    for (x, y, pixel) in code_img.enumerate_pixels_mut() {
+        if !columns.contains(&x) || !rows.contains(&y) {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    let mut canvas: RgbaImage = image::open(bg_path)
+        .map_err(|e| format!("Failed to open background image '{}': {}", bg_path.display(), e))?
+        .into_rgba8();
+
+    if canvas.width() < code_img.width() || canvas.height() < code_img.height() {
+        return Err(format!(
+            "Background image is {}x{} but the QR code needs at least {}x{} pixels",
+            canvas.width(), canvas.height(), code_img.width(), code_img.height()));
+    }
+
+    let left: u32 = (canvas.width() - code_img.width()) / 2;
+    let top: u32 = (canvas.height() - code_img.height()) / 2;
+
+    // The quiet zone may extend past the drawn border into the background, so measure it there
+    let symbol_left: i64 = (left + border as u32 * scale_factor) as i64;
+    let symbol_top: i64 = (top + border as u32 * scale_factor) as i64;
+    let symbol_px: (i64, i64) = ((qr.width() as u32 * scale_factor) as i64, (qr.height() as u32 * scale_factor) as i64);
+    let clearance: i32 = quiet_zone_clearance(&canvas, symbol_left, symbol_top, symbol_px, scale_factor as i64, qr.quiet_zone());
+    if clearance < qr.quiet_zone() {
+        report_quiet_zone(&format!("the background image intrudes {} modules into the {}-module quiet zone.",
+            qr.quiet_zone() - clearance, qr.quiet_zone()), strict);
+    }
+
+    // Luminance statistics of the area the code will cover
+    let area = image::imageops::crop_imm(&canvas, left, top, code_img.width(), code_img.height()).to_image();
+    let lumas: Vec<f64> = image::DynamicImage::ImageRgba8(area).into_luma8().pixels().map(|p| p.0[0] as f64).collect();
+    let mean: f64 = lumas.iter().sum::<f64>() / lumas.len() as f64;
+    let deviation: f64 = (lumas.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / lumas.len() as f64).sqrt();
+    if mean < 180.0 {
+        eprintln!("Warning: the background behind the QR code is dark (mean luminance {:.0}/255). It may not scan.", mean);
+    }
+    if deviation > 32.0 {
+        eprintln!("Warning: the background behind the QR code is not uniform (luminance deviation {:.0}). It may not scan.", deviation);
+    }
+
+    image::imageops::overlay(&mut canvas, &code_img, left as i64, top as i64);
+
+    // Make sure the composited image still decodes to the original payload
+    if !decodes_to(&canvas, payload) {
+        eprintln!("Warning: the QR code could not be decoded from the composited image.");
+    }
+
+    let (width, height) = canvas.dimensions();
+    encode_png(width, height, png::ColorType::Rgba, png::BitDepth::Eight, &[], canvas.as_raw(), info)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rows are padded to whole bytes, so the bitmap is exactly stride * height bytes for any width.
+    #[test]
+    fn raw1_bitmap_is_stride_times_height() {
+        let segs: Vec<QrSegment> = QrSegment::make_segments("Hello World!");
+        let qr: QrCode = QrCode::encode_segments(&segs, QrCodeEcc::Medium).unwrap();
+        // rMQR codes are several times wider than tall, so mixing up the sides changes the length
+        let rmqr: rmqr::RmqrCode = rmqr::RmqrCode::encode(&segs, QrCodeEcc::Medium, Some(7), false).unwrap();
+        assert_ne!(rmqr.width(), rmqr.height());
+        for (border, scale) in [(0, 1), (4, 1), (1, 3), (4, 10)] {
+            for (bitmap, code_width, code_height) in [
+                (to_raw1_bitmap(&qr, border, scale, false), qr.width(), qr.height()),
+                (to_raw1_bitmap(&rmqr, border, scale, false), rmqr.width(), rmqr.height()),
+            ] {
+                let width: usize = ((code_width + 2 * border) * scale) as usize;
+                let height: usize = ((code_height + 2 * border) * scale) as usize;
+                let stride: usize = width.div_ceil(8);
+                assert_eq!(bitmap.len(), stride * height, "{}x{} border {} scale {}", code_width, code_height, border, scale);
+                // The padding bits at the end of each row stay clear
+                let padding: u8 = ((1u16 << (stride * 8 - width)) - 1) as u8;
+                assert!(bitmap.chunks(stride).all(|row| row[stride - 1] & padding == 0));
+            }
+        }
+    }
+
+    // Low contrast is refused unless --force-colors keeps it, and --strict refuses it regardless
+    #[test]
+    fn low_contrast_is_refused_by_default() {
+        assert!("#9aa0a6".parse::<Color>().unwrap().contrast(Color::WHITE) < MIN_CONTRAST);
+        assert!(refuses_contrast(false, false));
+        assert!(!refuses_contrast(true, false));
+        assert!(refuses_contrast(true, true));
+        assert!(refuses_contrast(false, true));
+    }
+
+    #[test]
+    fn null_separated_records() {
+        assert_eq!(split_records(b"one\0two\nlines\0three"), [&b"one"[..], b"two\nlines", b"three"]);
+        // A trailing NUL, as find -print0 writes, and empty records make no codes
+        assert_eq!(split_records(b"one\0\0two\0"), [&b"one"[..], b"two"]);
+        assert!(split_records(b"\0").is_empty());
+        assert_eq!(split_records(b"no separator"), [&b"no separator"[..]]);
+    }
 }
@@ -0,0 +1,77 @@
+use qrcodegen::QrCodeEcc;
+
+use crate::{Grid, spec};
+
+// Micro QR codes need a quiet zone of only 2 modules.
+pub const QUIET_ZONE: i32 = 2;
+
+
+// A Micro QR code, M1 to M4: a single finder pattern in the top-left corner and 11 to 17
+// modules on a side, for payloads of a few characters. The qrcode crate does the encoding.
+pub struct MicroCode {
+    version: u8,
+    ecc: QrCodeEcc,
+    size: i32,
+    // Whether each module is dark, row by row
+    modules: Vec<bool>,
+    // Whether each module belongs to a function pattern rather than the data
+    is_function: Vec<bool>,
+}
+
+impl MicroCode {
+    // Encodes the data in the smallest version that holds it at the given level. M1 only
+    // detects errors and is used for level L; the larger versions have no level H, and only M4 has Q.
+    pub fn encode(data: &[u8], ecc: QrCodeEcc) -> Result<MicroCode, String> {
+        let level: qrcode::EcLevel = match ecc {
+            QrCodeEcc::Low => qrcode::EcLevel::L,
+            QrCodeEcc::Medium => qrcode::EcLevel::M,
+            QrCodeEcc::Quartile => qrcode::EcLevel::Q,
+            QrCodeEcc::High => return Err("Micro QR codes have no error correction level H. Use L, M or Q.".to_string()),
+        };
+        for version in 1..=4 {
+            // A version that doesn't have the level fails like one that is too small
+            let Ok(code) = qrcode::QrCode::with_version(data, qrcode::Version::Micro(version), level) else { continue };
+            let modules: Vec<bool> = code.to_colors().into_iter().map(|color| color == qrcode::Color::Dark).collect();
+            let size: usize = code.width();
+            let is_function: Vec<bool> = (0..size * size).map(|i| code.is_functional(i % size, i / size)).collect();
+            return Ok(MicroCode { version: version as u8, ecc, size: size as i32, modules, is_function });
+        }
+        Err(format!("The data does not fit in a Micro QR code at ECC {}.", spec::ecc_letter(ecc)))
+    }
+}
+
+impl Grid for MicroCode {
+    fn width(&self) -> i32 {
+        self.size
+    }
+
+    fn height(&self) -> i32 {
+        self.size
+    }
+
+    // Modules outside the symbol are light, as with qrcodegen.
+    fn get_module(&self, x: i32, y: i32) -> bool {
+        (0..self.size).contains(&x) && (0..self.size).contains(&y) && self.modules[(y * self.size + x) as usize]
+    }
+
+    fn is_function_module(&self, x: i32, y: i32) -> bool {
+        (0..self.size).contains(&x) && (0..self.size).contains(&y) && self.is_function[(y * self.size + x) as usize]
+    }
+
+    // The only finder pattern is in the top-left corner.
+    fn finder_positions(&self) -> Vec<(i32, i32)> {
+        vec![(0, 0)]
+    }
+
+    fn version_name(&self) -> String {
+        format!("M{}", self.version)
+    }
+
+    fn error_correction_level(&self) -> QrCodeEcc {
+        self.ecc
+    }
+
+    fn quiet_zone(&self) -> i32 {
+        QUIET_ZONE
+    }
+}
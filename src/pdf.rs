@@ -0,0 +1,101 @@
+use crate::caption::Caption;
+use crate::{Grid, PageSize, dark_runs, svg_number};
+
+// PDF user space units per inch.
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+
+// Returns a one-page PDF with the QR code drawn as filled rectangles, `scale` points per module,
+// and the caption if any under it. Without a page size the page fits the code plus the margin;
+// otherwise the code is centered. Inverting swaps black and white within the code.
+pub fn to_pdf_bytes(qr: &impl Grid, border: f64, scale: f64, page: Option<PageSize>, margin: f64, invert: bool, caption: Option<&Caption>) -> Result<Vec<u8>, String> {
+    let (code_width, code_height) = ((qr.width() as f64 + border * 2.0) * scale, (qr.height() as f64 + border * 2.0) * scale);
+    let symbol: f64 = qr.width() as f64 * scale;
+    let band: f64 = caption.map_or(0.0, |caption| caption.band_height(symbol, scale));
+    let (width, height) = match page {
+        Some(page) => {
+            let (width, height) = page.inches();
+            (width * POINTS_PER_INCH, height * POINTS_PER_INCH)
+        }
+        None => (code_width + margin * 2.0, code_height + band + margin * 2.0),
+    };
+    if code_width + margin * 2.0 > width || code_height + band + margin * 2.0 > height {
+        return Err(format!("The code is {:.1}mm wide and does not fit on the page with its margins.", code_width / POINTS_PER_INCH * 25.4));
+    }
+
+    let left: f64 = (width - code_width) / 2.0;
+    let top: f64 = (height - code_height - band) / 2.0;
+    let mut content: String = code_content(qr, border, scale, left, top, height, invert);
+    if let Some(caption) = caption {
+        // The caption is printed on the page, black on white
+        content += "0 g\n";
+        content += &caption.pdf_content(left + border * scale, top + code_height, symbol, scale, height);
+    }
+    Ok(document(&[(width, height, content)]))
+}
+
+
+// Returns the drawing operators for the code with its top-left corner at (left, top),
+// measured from the top of a page of the given height. Dark runs in a row become one rectangle.
+// Inverting draws them white on black.
+pub fn code_content(qr: &impl Grid, border: f64, scale: f64, left: f64, top: f64, page_height: f64, invert: bool) -> String {
+    let (width, height) = ((qr.width() as f64 + border * 2.0) * scale, (qr.height() as f64 + border * 2.0) * scale);
+    let (dark, light) = if invert { (1, 0) } else { (0, 1) };
+    // PDF measures y upwards from the bottom of the page
+    let mut content: String = format!("{} g {} {} {} {} re f\n{} g\n",
+        light, svg_number(left), svg_number(page_height - top - height), svg_number(width), svg_number(height), dark);
+
+    for (x, y, length) in dark_runs(qr) {
+        content += &format!("{} {} {} {} re\n",
+            svg_number(left + (x as f64 + border) * scale),
+            svg_number(page_height - top - (y as f64 + border + 1.0) * scale),
+            svg_number(length as f64 * scale),
+            svg_number(scale));
+    }
+    content += "f\n";
+    content
+}
+
+
+// Assembles a PDF document from pages given as width, height (in points) and content stream.
+pub fn document(pages: &[(f64, f64, String)]) -> Vec<u8> {
+    // Objects 1 and 2 are the catalog and the page tree; each page adds a page and a content object
+    let page_refs: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 3 + i * 2)).collect();
+    let mut objects: Vec<String> = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", page_refs.join(" "), pages.len()),
+    ];
+    for (i, (width, height, content)) in pages.iter().enumerate() {
+        objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents {} 0 R /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >> >> >>",
+            svg_number(*width), svg_number(*height), 4 + i * 2));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+    }
+
+    let mut pdf: String = "%PDF-1.4\n".to_string();
+    let mut offsets: Vec<usize> = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf += &format!("{} 0 obj\n{}\nendobj\n", i + 1, object);
+    }
+
+    let xref: usize = pdf.len();
+    pdf += &format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        pdf += &format!("{:010} 00000 n \n", offset);
+    }
+    pdf += &format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref);
+    pdf.into_bytes()
+}
+
+
+// Escapes text for a PDF string literal. Characters outside ASCII are replaced with '?',
+// since the standard fonts only cover Latin text.
+pub fn escape_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' | '(' | ')' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,104 @@
+use qrcodegen::QrCode;
+
+
+// Penalty weights from the QR Code specification (ISO/IEC 18004, section 7.8.3).
+const PENALTY_N1: i32 = 3;
+const PENALTY_N2: i32 = 3;
+const PENALTY_N3: i32 = 40;
+const PENALTY_N4: i32 = 10;
+
+
+// Returns the mask penalty of the given QrCode object split by rule:
+// runs of same-colored modules, 2x2 blocks, finder-like patterns and dark/light balance.
+// The score is computed the same way qrcodegen does when it picks a mask automatically.
+pub fn penalty_scores(qr: &QrCode) -> [i32; 4] {
+    let size: i32 = qr.size();
+    let mut runs: i32 = 0;
+    let mut finders: i32 = 0;
+
+    // Rows and columns are scanned the same way, only with x and y swapped
+    for transpose in [false, true] {
+        for a in 0..size {
+            let module = |b: i32| if transpose { qr.get_module(a, b) } else { qr.get_module(b, a) };
+            let mut run_color = false;
+            let mut run_length: i32 = 0;
+            let mut history = RunHistory::new(size);
+            for b in 0..size {
+                if module(b) == run_color {
+                    run_length += 1;
+                    if run_length == 5 {
+                        runs += PENALTY_N1;
+                    } else if run_length > 5 {
+                        runs += 1;
+                    }
+                } else {
+                    history.add(run_length);
+                    if !run_color {
+                        finders += history.count_finder_patterns() * PENALTY_N3;
+                    }
+                    run_color = module(b);
+                    run_length = 1;
+                }
+            }
+            finders += history.terminate_and_count(run_color, run_length) * PENALTY_N3;
+        }
+    }
+
+    let mut blocks: i32 = 0;
+    for y in 0..size - 1 {
+        for x in 0..size - 1 {
+            let color: bool = qr.get_module(x, y);
+            if color == qr.get_module(x + 1, y) && color == qr.get_module(x, y + 1) && color == qr.get_module(x + 1, y + 1) {
+                blocks += PENALTY_N2;
+            }
+        }
+    }
+
+    // Smallest k >= 0 such that (45-5k)% <= dark/total <= (55+5k)%
+    let total: i32 = size * size;
+    let dark: i32 = (0..size).flat_map(|y| (0..size).map(move |x| (x, y))).filter(|&(x, y)| qr.get_module(x, y)).count() as i32;
+    let k: i32 = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+    let balance: i32 = k * PENALTY_N4;
+
+    [runs, blocks, finders, balance]
+}
+
+
+// Lengths of the last seven runs in a row or column, used to spot 1:1:3:1:1 finder-like patterns.
+// The area outside the symbol counts as a light run as wide as the symbol.
+struct RunHistory {
+    size: i32,
+    runs: [i32; 7],
+}
+
+impl RunHistory {
+    fn new(size: i32) -> Self {
+        RunHistory { size, runs: [0; 7] }
+    }
+
+    fn add(&mut self, mut run_length: i32) {
+        if self.runs[0] == 0 {
+            run_length += self.size; // Light border before the first run
+        }
+        self.runs.copy_within(0..6, 1);
+        self.runs[0] = run_length;
+    }
+
+    // Counts dark-light-dark-light-dark runs of 1:1:3:1:1 with 4 light modules on either side.
+    fn count_finder_patterns(&self) -> i32 {
+        let r = &self.runs;
+        let n: i32 = r[1];
+        let core: bool = n > 0 && r[2] == n && r[3] == n * 3 && r[4] == n && r[5] == n;
+        i32::from(core && r[0] >= n * 4 && r[6] >= n) + i32::from(core && r[6] >= n * 4 && r[0] >= n)
+    }
+
+    fn terminate_and_count(mut self, run_color: bool, mut run_length: i32) -> i32 {
+        if run_color {
+            self.add(run_length);
+            run_length = 0;
+        }
+        run_length += self.size; // Light border after the last run
+        self.add(run_length);
+        self.count_finder_patterns()
+    }
+}
@@ -0,0 +1,492 @@
+use qrcodegen::{QrCodeEcc, QrSegment, QrSegmentMode};
+
+use crate::{Grid, spec};
+
+// rMQR codes need a quiet zone of only 2 modules.
+pub const QUIET_ZONE: i32 = 2;
+
+// Heights the versions come in, in modules.
+pub const HEIGHTS: [u8; 6] = [7, 9, 11, 13, 15, 17];
+
+// A version's height and width, its data codewords and error correction blocks at levels M and H,
+// and its character count bits in numeric, alphanumeric, byte and kanji mode.
+type VersionInfo = (u8, u8, [usize; 2], [usize; 2], [usize; 4]);
+
+// The versions from ISO/IEC 23941. The version indicator in the format information is the index in this table.
+const VERSIONS: [VersionInfo; 32] = [
+    (7, 43, [6, 3], [1, 1], [4, 3, 3, 2]),
+    (7, 59, [12, 7], [1, 1], [5, 5, 4, 3]),
+    (7, 77, [20, 10], [1, 1], [6, 5, 5, 4]),
+    (7, 99, [28, 14], [1, 1], [7, 6, 5, 5]),
+    (7, 139, [44, 24], [1, 2], [7, 6, 6, 5]),
+    (9, 43, [12, 7], [1, 1], [5, 5, 4, 3]),
+    (9, 59, [21, 11], [1, 1], [6, 5, 5, 4]),
+    (9, 77, [31, 17], [1, 2], [7, 6, 5, 5]),
+    (9, 99, [42, 22], [1, 2], [7, 6, 6, 5]),
+    (9, 139, [63, 33], [2, 3], [8, 7, 6, 6]),
+    (11, 27, [7, 5], [1, 1], [4, 4, 3, 2]),
+    (11, 43, [19, 11], [1, 1], [6, 5, 5, 4]),
+    (11, 59, [31, 15], [1, 2], [7, 6, 5, 5]),
+    (11, 77, [43, 23], [1, 2], [7, 6, 6, 5]),
+    (11, 99, [57, 29], [2, 2], [8, 7, 6, 6]),
+    (11, 139, [84, 42], [2, 3], [8, 7, 7, 6]),
+    (13, 27, [12, 7], [1, 1], [5, 5, 4, 3]),
+    (13, 43, [27, 13], [1, 1], [6, 6, 5, 5]),
+    (13, 59, [38, 20], [1, 2], [7, 6, 6, 5]),
+    (13, 77, [53, 29], [2, 2], [7, 7, 6, 5]),
+    (13, 99, [73, 35], [2, 3], [8, 7, 7, 6]),
+    (13, 139, [106, 54], [3, 4], [8, 8, 7, 7]),
+    (15, 43, [33, 15], [1, 2], [7, 6, 6, 5]),
+    (15, 59, [48, 26], [1, 2], [7, 7, 6, 5]),
+    (15, 77, [67, 31], [2, 3], [8, 7, 7, 6]),
+    (15, 99, [88, 48], [2, 4], [8, 7, 7, 6]),
+    (15, 139, [127, 69], [3, 5], [9, 8, 7, 7]),
+    (17, 43, [39, 21], [1, 2], [7, 6, 6, 5]),
+    (17, 59, [56, 28], [2, 2], [8, 7, 6, 6]),
+    (17, 77, [78, 38], [2, 3], [8, 7, 7, 6]),
+    (17, 99, [100, 56], [3, 4], [8, 8, 7, 6]),
+    (17, 139, [152, 76], [4, 6], [9, 8, 8, 7]),
+];
+
+// Centers of the alignment patterns along the width, where vertical timing patterns run from top to bottom.
+fn alignment_columns(width: u8) -> &'static [i32] {
+    match width {
+        43 => &[21],
+        59 => &[19, 39],
+        77 => &[25, 51],
+        99 => &[23, 49, 75],
+        139 => &[27, 55, 83, 111],
+        _ => &[],
+    }
+}
+
+// Masks that keep the format information on each side from reading the same.
+const FORMAT_MASK_FINDER: u32 = 0b011111101010110010;
+const FORMAT_MASK_SUB_FINDER: u32 = 0b100000101001111011;
+
+
+// A rectangular Micro QR code, 7 to 17 modules tall and 27 to 139 wide, for narrow labels:
+// a finder pattern on the left, a smaller one in the bottom-right corner, and only levels M and H.
+pub struct RmqrCode {
+    version: usize,
+    ecc: QrCodeEcc,
+    width: i32,
+    height: i32,
+    // Whether each module is dark, row by row
+    modules: Vec<bool>,
+    // Whether each module belongs to a function pattern rather than the data
+    is_function: Vec<bool>,
+}
+
+impl RmqrCode {
+    // Encodes the segments in the smallest symbol that holds them, of the given height if any.
+    // Like qrcodegen, M is raised to H when the symbol still holds the data, unless told not to.
+    pub fn encode(segs: &[QrSegment], ecc: QrCodeEcc, height: Option<u8>, boost_ecc: bool) -> Result<RmqrCode, String> {
+        let level: usize = match ecc {
+            QrCodeEcc::Medium => 0,
+            QrCodeEcc::High => 1,
+            _ => return Err("rMQR codes only have error correction levels M and H.".to_string()),
+        };
+        let version: usize = (0..VERSIONS.len())
+            .filter(|&version| height.is_none_or(|height| VERSIONS[version].0 == height))
+            .filter(|&version| fits(segs, version, level))
+            .min_by_key(|&version| VERSIONS[version].0 as usize * VERSIONS[version].1 as usize)
+            .ok_or_else(|| match height {
+                Some(height) => format!("The data does not fit in an rMQR code {} modules tall at ECC {}.", height, spec::ecc_letter(ecc)),
+                None => format!("The data does not fit in an rMQR code at ECC {}.", spec::ecc_letter(ecc)),
+            })?;
+        let level: usize = if boost_ecc && fits(segs, version, 1) { 1 } else { level };
+
+        let mut code = RmqrCode::new(version, level);
+        code.draw_codewords(&add_ecc_and_interleave(&data_codewords(segs, version, level), version, level, code.capacity()));
+        code.apply_mask();
+        Ok(code)
+    }
+
+    // Returns a symbol of the version with its function patterns and format information drawn, but no data yet.
+    fn new(version: usize, level: usize) -> RmqrCode {
+        let (height, width, ..) = VERSIONS[version];
+        let mut code = RmqrCode {
+            version,
+            ecc: if level == 0 { QrCodeEcc::Medium } else { QrCodeEcc::High },
+            width: width as i32,
+            height: height as i32,
+            modules: vec![false; height as usize * width as usize],
+            is_function: vec![false; height as usize * width as usize],
+        };
+        code.draw_function_patterns();
+        code.draw_format_bits(level);
+        code
+    }
+
+    // Returns the number of whole codewords that fit in the modules left for data.
+    fn capacity(&self) -> usize {
+        self.is_function.iter().filter(|&&function| !function).count() / 8
+    }
+
+    fn set_function_module(&mut self, x: i32, y: i32, dark: bool) {
+        let index: usize = (y * self.width + x) as usize;
+        self.modules[index] = dark;
+        self.is_function[index] = true;
+    }
+
+    // Draws the timing patterns, then the finder, separator, corner and alignment patterns over them.
+    fn draw_function_patterns(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let columns: &[i32] = alignment_columns(width as u8);
+
+        // Timing patterns along the top and bottom edges, the left and right edges and each alignment column
+        for x in 0..width {
+            self.set_function_module(x, 0, x % 2 == 0);
+            self.set_function_module(x, height - 1, x % 2 == 0);
+        }
+        for &x in [0, width - 1].iter().chain(columns) {
+            for y in 0..height {
+                self.set_function_module(x, y, (x + y) % 2 == 0);
+            }
+        }
+
+        // The finder pattern with its separator, which only has a bottom edge when the symbol is taller than it
+        for y in 0..height.min(8) {
+            for x in 0..8 {
+                let distance: i32 = i32::abs(x - 3).max(i32::abs(y - 3));
+                self.set_function_module(x, y, distance != 2 && distance != 4);
+            }
+        }
+
+        // The finder sub-pattern in the bottom-right corner
+        for dy in -2..=2 {
+            for dx in -2..=2 {
+                let distance: i32 = i32::abs(dx).max(i32::abs(dy));
+                self.set_function_module(width - 3 + dx, height - 3 + dy, distance != 1);
+            }
+        }
+
+        // The corner finder sub-patterns in the top-right and bottom-left corners
+        for (x, y, dark) in [(width - 3, 0, true), (width - 2, 0, true), (width - 1, 0, true), (width - 2, 1, false), (width - 1, 1, true)] {
+            self.set_function_module(x, y, dark);
+        }
+        for x in 0..3 {
+            self.set_function_module(x, height - 1, true);
+        }
+        if height >= 11 {
+            self.set_function_module(0, height - 2, true);
+            self.set_function_module(1, height - 2, false);
+        }
+
+        // Alignment patterns at the top and bottom of each alignment column
+        for &center in columns {
+            for dy in 0..3 {
+                for dx in -1..=1 {
+                    let dark: bool = dx != 0 || dy != 1;
+                    self.set_function_module(center + dx, dy, dark);
+                    self.set_function_module(center + dx, height - 1 - dy, dark);
+                }
+            }
+        }
+    }
+
+    // Draws the level and version with their BCH error correction twice, next to the finder pattern and above the sub-pattern.
+    fn draw_format_bits(&mut self, level: usize) {
+        let data: u32 = (level as u32) << 5 | self.version as u32;
+        let mut rem: u32 = data;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits: u32 = data << 12 | rem;
+
+        let (finder, sub_finder) = (bits ^ FORMAT_MASK_FINDER, bits ^ FORMAT_MASK_SUB_FINDER);
+        for i in 0..15 {
+            self.set_function_module(8 + i / 5, 1 + i % 5, finder >> i & 1 != 0);
+            self.set_function_module(self.width - 8 + i / 5, self.height - 6 + i % 5, sub_finder >> i & 1 != 0);
+        }
+        for i in 0..3 {
+            self.set_function_module(11, 1 + i, finder >> (15 + i) & 1 != 0);
+            self.set_function_module(self.width - 5 + i, self.height - 6, sub_finder >> (15 + i) & 1 != 0);
+        }
+    }
+
+    // Draws the codewords in the zigzag of QR codes, two columns at a time from the right, starting upwards.
+    // The remainder bits after the last codeword stay light.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut i: usize = 0;
+        let mut right: i32 = self.width - 2;
+        while right >= 1 {
+            let upward: bool = (self.width - 2 - right) % 4 == 0;
+            for vert in 0..self.height {
+                let y: i32 = if upward { self.height - 1 - vert } else { vert };
+                for x in [right, right - 1] {
+                    let index: usize = (y * self.width + x) as usize;
+                    if !self.is_function[index] && i < codewords.len() * 8 {
+                        self.modules[index] = codewords[i / 8] >> (7 - i % 8) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    // rMQR codes always use the one mask pattern, which flips the data modules where (y / 2 + x / 3) is even.
+    fn apply_mask(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index: usize = (y * self.width + x) as usize;
+                if !self.is_function[index] && (y / 2 + x / 3) % 2 == 0 {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+}
+
+impl Grid for RmqrCode {
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    // Modules outside the symbol are light, as with qrcodegen.
+    fn get_module(&self, x: i32, y: i32) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y) && self.modules[(y * self.width + x) as usize]
+    }
+
+    fn is_function_module(&self, x: i32, y: i32) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y) && self.is_function[(y * self.width + x) as usize]
+    }
+
+    // The finder sub-pattern in the bottom-right corner is smaller and has no eye.
+    fn finder_positions(&self) -> Vec<(i32, i32)> {
+        vec![(0, 0)]
+    }
+
+    // Returns the version name, e.g. R7x43 for 7 modules tall and 43 wide.
+    fn version_name(&self) -> String {
+        format!("R{}x{}", self.height, self.width)
+    }
+
+    fn error_correction_level(&self) -> QrCodeEcc {
+        self.ecc
+    }
+
+    fn quiet_zone(&self) -> i32 {
+        QUIET_ZONE
+    }
+}
+
+
+// Returns the character count bits of the mode in the given version. ECI segments have no count.
+fn count_bits(mode: QrSegmentMode, version: usize) -> usize {
+    let bits: [usize; 4] = VERSIONS[version].4;
+    match mode {
+        QrSegmentMode::Numeric => bits[0],
+        QrSegmentMode::Alphanumeric => bits[1],
+        QrSegmentMode::Byte => bits[2],
+        QrSegmentMode::Kanji => bits[3],
+        QrSegmentMode::Eci => 0,
+    }
+}
+
+
+// Returns the 3-bit mode indicator, shorter than the 4 bits of QR codes.
+fn mode_indicator(mode: QrSegmentMode) -> u32 {
+    match mode {
+        QrSegmentMode::Numeric => 0b001,
+        QrSegmentMode::Alphanumeric => 0b010,
+        QrSegmentMode::Byte => 0b011,
+        QrSegmentMode::Kanji => 0b100,
+        QrSegmentMode::Eci => 0b111,
+    }
+}
+
+
+// Returns whether the segments fit in the data codewords of the version at the level,
+// with every character count small enough for its field.
+fn fits(segs: &[QrSegment], version: usize, level: usize) -> bool {
+    let mut bits: usize = 0;
+    for seg in segs {
+        let count: usize = count_bits(seg.mode(), version);
+        if seg.num_chars() >= 1 << count && seg.mode() != QrSegmentMode::Eci {
+            return false;
+        }
+        bits += 3 + count + seg.data().len();
+    }
+    bits <= VERSIONS[version].2[level] * 8
+}
+
+
+// Returns the data codewords: each segment with its mode indicator and character count,
+// the terminator and the pad bytes that fill the version's capacity at the level.
+fn data_codewords(segs: &[QrSegment], version: usize, level: usize) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    for seg in segs {
+        spec::append_bits(&mut bits, mode_indicator(seg.mode()), 3);
+        spec::append_bits(&mut bits, seg.num_chars() as u32, count_bits(seg.mode(), version));
+        bits.extend_from_slice(seg.data());
+    }
+
+    // rMQR codes end the data with up to three zero bits instead of four
+    spec::pad_codewords(bits, 3, VERSIONS[version].2[level])
+}
+
+
+// Splits the data codewords into blocks, shorter ones first, adds Reed-Solomon error correction
+// to each, and interleaves them as QR codes do, to the symbol's total of `capacity` codewords.
+fn add_ecc_and_interleave(data: &[u8], version: usize, level: usize, capacity: usize) -> Vec<u8> {
+    let num_blocks: usize = VERSIONS[version].3[level];
+    let block_ecc_len: usize = (capacity - data.len()) / num_blocks;
+    let num_short_blocks: usize = num_blocks - capacity % num_blocks;
+    let short_block_len: usize = capacity / num_blocks;
+
+    let divisor: Vec<u8> = reed_solomon_divisor(block_ecc_len);
+    let mut blocks: Vec<Vec<u8>> = Vec::with_capacity(num_blocks);
+    let mut start: usize = 0;
+    for i in 0..num_blocks {
+        let data_len: usize = short_block_len - block_ecc_len + usize::from(i >= num_short_blocks);
+        let mut block: Vec<u8> = data[start..start + data_len].to_vec();
+        start += data_len;
+        let ecc: Vec<u8> = reed_solomon_remainder(&block, &divisor);
+        // Short blocks get a gap where the long ones have their extra data codeword
+        if i < num_short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+
+    let mut result: Vec<u8> = Vec::with_capacity(capacity);
+    for i in 0..=short_block_len {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - block_ecc_len || j >= num_short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+
+// Returns the coefficients of the Reed-Solomon generator polynomial of the given degree,
+// highest power first without the leading 1, over GF(2^8) with the QR code polynomial 0x11D.
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result: Vec<u8> = vec![0; degree - 1];
+    result.push(1);
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = reed_solomon_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = reed_solomon_multiply(root, 0x02);
+    }
+    result
+}
+
+
+// Returns the error correction codewords of the data for the given generator polynomial.
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result: Vec<u8> = vec![0; divisor.len()];
+    for &b in data {
+        let factor: u8 = b ^ result.remove(0);
+        result.push(0);
+        for (x, &y) in result.iter_mut().zip(divisor) {
+            *x ^= reed_solomon_multiply(y, factor);
+        }
+    }
+    result
+}
+
+
+// Multiplies two elements of GF(2^8) modulo 0x11D.
+fn reed_solomon_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Total codewords of each version in the order of VERSIONS, as published for rMQR.
+    const TOTAL_CODEWORDS: [usize; 32] = [
+        13, 21, 32, 44, 68, 21, 33, 49, 66, 99, 15, 31, 47, 67, 89, 132,
+        21, 41, 60, 85, 113, 166, 51, 74, 103, 136, 199, 61, 88, 122, 160, 232,
+    ];
+
+    // The function patterns leave exactly the published number of codewords for the data.
+    #[test]
+    fn layout_holds_the_total_codewords() {
+        for (version, &total) in TOTAL_CODEWORDS.iter().enumerate() {
+            let code = RmqrCode::new(version, 0);
+            assert_eq!(code.capacity(), total, "{}", code.version_name());
+            assert_eq!(code.version_name(), format!("R{}x{}", VERSIONS[version].0, VERSIONS[version].1));
+        }
+    }
+
+    // Every block of a version and level gets the same number of error correction codewords.
+    #[test]
+    fn error_correction_blocks_are_even() {
+        for (version, &total) in TOTAL_CODEWORDS.iter().enumerate() {
+            for level in 0..2 {
+                let (data, blocks) = (VERSIONS[version].2[level], VERSIONS[version].3[level]);
+                assert!(data < total, "R{}x{} level {}", VERSIONS[version].0, VERSIONS[version].1, level);
+                assert_eq!((total - data) % blocks, 0, "R{}x{} level {}", VERSIONS[version].0, VERSIONS[version].1, level);
+            }
+        }
+    }
+
+    // Both copies of the format information read back as the level and version, and are
+    // codewords of the (18, 6) BCH code once the mask is taken off.
+    #[test]
+    fn format_information_reads_back() {
+        for version in 0..VERSIONS.len() {
+            for level in 0..2 {
+                let code = RmqrCode::new(version, level);
+                let (width, height) = (code.width, code.height);
+                let read = |positions: &[(i32, i32)]| -> u32 {
+                    positions.iter().enumerate().map(|(i, &(x, y))| u32::from(code.get_module(x, y)) << i).sum()
+                };
+                let finder: Vec<(i32, i32)> = (0..15).map(|i| (8 + i / 5, 1 + i % 5)).chain((0..3).map(|i| (11, 1 + i))).collect();
+                let sub_finder: Vec<(i32, i32)> = (0..15).map(|i| (width - 8 + i / 5, height - 6 + i % 5))
+                    .chain((0..3).map(|i| (width - 5 + i, height - 6))).collect();
+                for bits in [read(&finder) ^ FORMAT_MASK_FINDER, read(&sub_finder) ^ FORMAT_MASK_SUB_FINDER] {
+                    assert_eq!(bits >> 12, (level as u32) << 5 | version as u32);
+                    let remainder: u32 = (6..18).rev().fold(bits, |rem, i| if rem >> i & 1 != 0 { rem ^ 0x1F25 << (i - 12) } else { rem });
+                    assert_eq!(remainder, 0, "{} level {}", code.version_name(), level);
+                }
+            }
+        }
+    }
+
+    // The symbol with the fewest modules is picked, and M is raised to H where that still fits.
+    #[test]
+    fn picks_the_smallest_symbol() {
+        let segs: Vec<QrSegment> = QrSegment::make_segments("12345");
+        let code = RmqrCode::encode(&segs, QrCodeEcc::Medium, None, false).unwrap();
+        assert_eq!((code.version_name(), code.ecc), ("R11x27".to_string(), QrCodeEcc::Medium));
+        let code = RmqrCode::encode(&segs, QrCodeEcc::Medium, Some(13), true).unwrap();
+        assert_eq!((code.version_name(), code.ecc), ("R13x27".to_string(), QrCodeEcc::High));
+        assert!(RmqrCode::encode(&segs, QrCodeEcc::Low, None, false).is_err());
+        let long: Vec<QrSegment> = QrSegment::make_segments(&"9".repeat(400));
+        assert!(RmqrCode::encode(&long, QrCodeEcc::High, Some(7), false).is_err());
+    }
+
+    // rMQR uses the Reed-Solomon code of QR codes, so the worked example of ISO/IEC 18004 Annex I,
+    // 01234567 in version 1-M, gives its error correction codewords.
+    #[test]
+    fn reed_solomon_matches_the_published_example() {
+        let data: [u8; 16] = [0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11];
+        assert_eq!(reed_solomon_remainder(&data, &reed_solomon_divisor(10)), [0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55]);
+    }
+}
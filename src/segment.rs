@@ -0,0 +1,290 @@
+use encoding_rs::SHIFT_JIS;
+use qrcodegen::{DataTooLong, Mask, QrCode, QrCodeEcc, QrSegment, QrSegmentMode, Version};
+
+use crate::spec;
+
+// The character count fields widen at versions 10 and 27, which can change the best split,
+// so each range of versions gets its own segments.
+const VERSION_RANGES: [(u8, u8); 3] = [(1, 9), (10, 26), (27, 40)];
+
+// Modes a character can be encoded in, from the most to the least general.
+const MODES: [QrSegmentMode; 4] = [QrSegmentMode::Byte, QrSegmentMode::Alphanumeric, QrSegmentMode::Numeric, QrSegmentMode::Kanji];
+
+
+// Character sets for the bytes of text, announced to scanners with an ECI segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Charset {
+    Latin1,
+    ShiftJis,
+    #[default]
+    Utf8,
+}
+
+impl std::str::FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "latin1" | "iso-8859-1" => Ok(Charset::Latin1),
+            "shift_jis" | "sjis" => Ok(Charset::ShiftJis),
+            "utf8" | "utf-8" => Ok(Charset::Utf8),
+            _ => Err(format!("Unknown charset: {}. Use latin1, shift_jis or utf8", s)),
+        }
+    }
+}
+
+impl Charset {
+    // Returns the ECI assignment number of the character set.
+    pub fn eci(self) -> u32 {
+        match self {
+            Charset::Latin1 => 3,
+            Charset::ShiftJis => 20,
+            Charset::Utf8 => 26,
+        }
+    }
+
+    // Returns the text in the character set, or the first character it has no code for.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, char> {
+        match self {
+            Charset::Latin1 => text.chars().map(|c| u8::try_from(c).map_err(|_| c)).collect(),
+            Charset::ShiftJis => {
+                let mut bytes: Vec<u8> = Vec::new();
+                for c in text.chars() {
+                    let mut buffer: [u8; 4] = [0; 4];
+                    let (encoded, _, unmappable) = SHIFT_JIS.encode(c.encode_utf8(&mut buffer));
+                    if unmappable {
+                        return Err(c);
+                    }
+                    bytes.extend_from_slice(&encoded);
+                }
+                Ok(bytes)
+            }
+            Charset::Utf8 => Ok(text.as_bytes().to_vec()),
+        }
+    }
+}
+
+
+// Encodes the segments made for each range of versions between min and max in turn and returns
+// the first code that fits, with its segments. Segments that don't depend on the version work the same.
+pub fn encode(segments: impl Fn(u8) -> Vec<QrSegment>, ecc: QrCodeEcc, min: Version, max: Version, mask: Option<Mask>, boost_ecc: bool)
+    -> Result<(QrCode, Vec<QrSegment>), DataTooLong> {
+    let mut error: Option<DataTooLong> = None;
+    for (low, high) in VERSION_RANGES {
+        let (low, high) = (low.max(min.value()), high.min(max.value()));
+        if low > high {
+            continue;
+        }
+        let segs: Vec<QrSegment> = segments(high);
+        match QrCode::encode_segments_advanced(&segs, ecc, Version::new(low), Version::new(high), mask, boost_ecc) {
+            Ok(qr) => return Ok((qr, segs)),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.expect("the version range is not empty"))
+}
+
+
+// Encodes text like QrCode::encode_text, but in the segments for the smallest code unless told not to optimize.
+pub fn encode_text(text: &str, ecc: QrCodeEcc, optimize: bool) -> Result<QrCode, DataTooLong> {
+    encode(|version| make_segments(text, version, optimize, Charset::Utf8), ecc, Version::MIN, Version::MAX, None, true).map(|(qr, _)| qr)
+}
+
+
+// Returns the bytes a decoder reads back from the segments: the digits and characters of numeric
+// and alphanumeric segments, the bytes of byte segments as they are and kanji as Shift JIS.
+// ECI segments only announce a charset and add nothing.
+pub fn decoded_bytes(segs: &[QrSegment]) -> Vec<u8> {
+    const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+    let mut bytes: Vec<u8> = Vec::new();
+    for seg in segs {
+        let data: &Vec<bool> = seg.data();
+        let value = |start: usize, len: usize| data[start..start + len].iter().fold(0usize, |acc, &bit| acc << 1 | bit as usize);
+        match seg.mode() {
+            QrSegmentMode::Numeric => {
+                // Three digits per 10 bits, with 7 or 4 bits for a shorter last group
+                let mut position: usize = 0;
+                for group in (0..seg.num_chars()).step_by(3) {
+                    let digits: usize = (seg.num_chars() - group).min(3);
+                    let len: usize = digits * 3 + 1;
+                    bytes.extend(format!("{:0width$}", value(position, len), width = digits).bytes());
+                    position += len;
+                }
+            }
+            QrSegmentMode::Alphanumeric => {
+                for pair in (0..seg.num_chars()).step_by(2) {
+                    if seg.num_chars() - pair == 1 {
+                        bytes.push(ALPHANUMERIC[value(pair / 2 * 11, 6)]);
+                    } else {
+                        let v: usize = value(pair / 2 * 11, 11);
+                        bytes.extend([ALPHANUMERIC[v / 45], ALPHANUMERIC[v % 45]]);
+                    }
+                }
+            }
+            QrSegmentMode::Byte => bytes.extend((0..seg.num_chars()).map(|i| value(i * 8, 8) as u8)),
+            QrSegmentMode::Kanji => {
+                for i in 0..seg.num_chars() {
+                    let v: usize = value(i * 13, 13);
+                    let code: usize = ((v / 0xC0) << 8) | (v % 0xC0);
+                    let code: u16 = (code + if code < 0x1F00 { 0x8140 } else { 0xC140 }) as u16;
+                    bytes.extend(code.to_be_bytes());
+                }
+            }
+            QrSegmentMode::Eci => {}
+        }
+    }
+    bytes
+}
+
+
+// Returns the segments for text at the given version: the shortest mix of modes, or
+// a single mode for the whole text as qrcodegen picks it when not optimizing.
+// Byte segments hold the text in the given charset, which must have a code for all of it.
+pub fn make_segments(text: &str, version: u8, optimize: bool, charset: Charset) -> Vec<QrSegment> {
+    let bytes = |text: &str| QrSegment::make_bytes(&charset.encode(text).expect("the text was checked against the charset"));
+    if !optimize {
+        return match charset {
+            Charset::Utf8 => QrSegment::make_segments(text),
+            _ if QrSegment::is_alphanumeric(text) => QrSegment::make_segments(text),
+            _ => vec![bytes(text)],
+        };
+    }
+    let modes: Vec<QrSegmentMode> = character_modes(text, version, charset);
+    let mut segs: Vec<QrSegment> = Vec::new();
+    let mut start: usize = 0;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for i in 1..=chars.len() {
+        // A run of characters in the same mode becomes one segment
+        if i < chars.len() && modes[i] == modes[start] {
+            continue;
+        }
+        let end: usize = chars.get(i).map_or(text.len(), |&(index, _)| index);
+        let run: &str = &text[chars[start].0..end];
+        segs.push(match modes[start] {
+            QrSegmentMode::Numeric => QrSegment::make_numeric(run),
+            QrSegmentMode::Alphanumeric => QrSegment::make_alphanumeric(run),
+            QrSegmentMode::Kanji => make_kanji(run),
+            _ => bytes(run),
+        });
+        start = i;
+    }
+    segs
+}
+
+
+// Returns the mode of each character of the text that gives the fewest bits in total,
+// counting a header for each change of mode. The costs are kept in sixths of a bit,
+// since numeric and alphanumeric characters take 10/3 and 11/2 bits each, and kanji 13.
+fn character_modes(text: &str, version: u8, charset: Charset) -> Vec<QrSegmentMode> {
+    let head_costs: [usize; 4] = MODES.map(|mode| (4 + spec::char_count_bits(mode, version)) * 6);
+    // For each character and the mode the text is in after it, the mode the character is encoded in
+    let mut char_modes: Vec<[Option<QrSegmentMode>; 4]> = Vec::new();
+    let mut costs: [usize; 4] = head_costs;
+
+    for c in text.chars() {
+        let mut modes: [Option<QrSegmentMode>; 4] = [None; 4];
+        let mut next: [usize; 4] = [usize::MAX; 4];
+        // Extend the segment of each mode that can hold the character
+        for (m, &mode) in MODES.iter().enumerate() {
+            let cost: Option<usize> = match mode {
+                QrSegmentMode::Byte => Some(charset.encode(c.encode_utf8(&mut [0; 4])).map_or(c.len_utf8(), |bytes| bytes.len()) * 8 * 6),
+                QrSegmentMode::Alphanumeric if QrSegment::is_alphanumeric(c.encode_utf8(&mut [0; 4])) => Some(33),
+                QrSegmentMode::Numeric if c.is_ascii_digit() => Some(20),
+                QrSegmentMode::Kanji if kanji_value(c).is_some() => Some(78),
+                _ => None,
+            };
+            if let Some(cost) = cost {
+                next[m] = costs[m] + cost;
+                modes[m] = Some(mode);
+            }
+        }
+        // Or end the segment after the character, rounded up to whole bits, and start one in another mode
+        for to in 0..MODES.len() {
+            for from in 0..MODES.len() {
+                if modes[from].is_none() {
+                    continue;
+                }
+                let cost: usize = next[from].div_ceil(6) * 6 + head_costs[to];
+                if modes[to].is_none() || cost < next[to] {
+                    next[to] = cost;
+                    modes[to] = Some(MODES[from]);
+                }
+            }
+        }
+        char_modes.push(modes);
+        costs = next;
+    }
+
+    // Trace back from the cheapest mode to end in
+    let mut mode: usize = (0..MODES.len()).min_by_key(|&m| costs[m]).unwrap_or(0);
+    let mut result: Vec<QrSegmentMode> = vec![QrSegmentMode::Byte; char_modes.len()];
+    for (i, modes) in char_modes.iter().enumerate().rev() {
+        let char_mode: QrSegmentMode = modes[mode].expect("every mode is reachable after a character");
+        result[i] = char_mode;
+        mode = MODES.iter().position(|&m| m == char_mode).unwrap_or(0);
+    }
+    result
+}
+
+
+// Returns a kanji mode segment for text whose characters all have a kanji value.
+fn make_kanji(text: &str) -> QrSegment {
+    let mut data: Vec<bool> = Vec::new();
+    for c in text.chars() {
+        let value: u16 = kanji_value(c).expect("the character is in the kanji ranges of Shift JIS");
+        data.extend((0..13).rev().map(|i| value >> i & 1 != 0));
+    }
+    QrSegment::new(QrSegmentMode::Kanji, text.chars().count(), data)
+}
+
+
+// Returns the 13-bit kanji mode value of a character, or none if its Shift JIS code isn't
+// a double-byte one in the ranges kanji mode covers, 0x8140-0x9FFC and 0xE040-0xEBBF.
+// Besides kanji these hold the kana, full-width letters and most Japanese punctuation.
+fn kanji_value(c: char) -> Option<u16> {
+    let mut buffer: [u8; 4] = [0; 4];
+    let (bytes, _, unmappable) = SHIFT_JIS.encode(c.encode_utf8(&mut buffer));
+    let [high, low] = *bytes else { return None };
+    if unmappable {
+        return None;
+    }
+    let code: u16 = u16::from_be_bytes([high, low]);
+    let offset: u16 = match code {
+        0x8140..=0x9FFC => code - 0x8140,
+        0xE040..=0xEBBF => code - 0xC140,
+        _ => return None,
+    };
+    Some((offset >> 8) * 0xC0 + (offset & 0xFF))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 7089 digits is the capacity of a version 40 code at level L, so one more digit must not fit.
+    #[test]
+    fn maximal_numeric_payload_fills_version_40() {
+        let digits: String = "0123456789".repeat(709)[..7089].to_string();
+        for optimize in [true, false] {
+            let qr: QrCode = encode_text(&digits, QrCodeEcc::Low, optimize).unwrap();
+            assert_eq!(qr.version().value(), 40);
+            assert_eq!(qr.error_correction_level(), QrCodeEcc::Low);
+            let longer: String = digits.clone() + "0";
+            assert!(matches!(encode_text(&longer, QrCodeEcc::Low, optimize), Err(DataTooLong::DataOverCapacity(_, _))));
+        }
+    }
+
+    #[test]
+    fn decoded_bytes_read_back_the_text() {
+        for text in ["", "0", "12", "123", "1234", "HELLO WORLD", "A", "https://example.com/?id=0123456789", "Ünïcödé 42"] {
+            for version in [1, 10, 27] {
+                assert_eq!(decoded_bytes(&make_segments(text, version, true, Charset::Utf8)), text.as_bytes(), "{}", text);
+            }
+        }
+        // Kanji mode holds Shift JIS, which decoders hand back as it is
+        let text: &str = "漢字とカナ";
+        let (shift_jis, _, _) = SHIFT_JIS.encode(text);
+        assert_eq!(decoded_bytes(&make_segments(text, 1, true, Charset::ShiftJis)), &shift_jis[..]);
+    }
+}
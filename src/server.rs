@@ -0,0 +1,121 @@
+use qrcodegen::{QrCode, QrCodeEcc};
+use std::io;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{PngInfo, SvgStyle, parse_ecc_levels, segment, to_png_bytes, to_svg_string};
+
+
+// Limits that keep a single request cheap. The payload limit is above the
+// largest QR code capacity, and border and scale bound the image size.
+const MAX_URL_LENGTH: usize = 8192;
+const MAX_BORDER: i32 = 20;
+const MAX_SCALE: i32 = 40;
+
+
+// Answers GET /qr?data=...&ecc=M&type=png|svg&border=4&scale=10 with the rendered code.
+pub fn serve(addr: &str) -> io::Result<()> {
+    let server = Server::http(addr).map_err(|e| io::Error::other(e.to_string()))?;
+    eprintln!("Listening on http://{}/qr?data=...", addr);
+
+    for request in server.incoming_requests() {
+        let (status, content_type, body) = handle(&request);
+        let response = Response::from_data(body)
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", content_type).expect("static header is valid"));
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to send response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+
+// Returns the status code, content type and body for a request.
+fn handle(request: &Request) -> (u16, &'static str, Vec<u8>) {
+    let error = |status: u16, message: &str| (status, "text/plain; charset=utf-8", format!("{}\n", message).into_bytes());
+
+    if request.method() != &Method::Get {
+        return error(405, "Only GET is supported.");
+    }
+    if request.url().len() > MAX_URL_LENGTH {
+        return error(414, "Request URL is too long.");
+    }
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    if path != "/qr" {
+        return error(404, "Not found. Use /qr?data=...");
+    }
+
+    let mut data: Option<String> = None;
+    let mut ecc: QrCodeEcc = QrCodeEcc::Medium;
+    let mut svg: bool = false;
+    let mut border: i32 = 4;
+    let mut scale: i32 = 10;
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value: String = match percent_decode(value) {
+            Some(value) => value,
+            None => return error(400, &format!("Invalid encoding in parameter '{}'.", key)),
+        };
+        match key {
+            "data" => data = Some(value),
+            "ecc" => match parse_ecc_levels(&value).as_deref() {
+                Some([level]) => ecc = *level,
+                _ => return error(400, "Invalid ecc. Use L, M, Q, or H."),
+            },
+            "type" => match value.to_lowercase().as_str() {
+                "png" => svg = false,
+                "svg" => svg = true,
+                _ => return error(400, "Invalid type. Use png or svg."),
+            },
+            "border" => match value.parse::<i32>() {
+                Ok(n) if (0..=MAX_BORDER).contains(&n) => border = n,
+                _ => return error(400, &format!("Invalid border. Use 0 to {}.", MAX_BORDER)),
+            },
+            "scale" => match value.parse::<i32>() {
+                Ok(n) if (1..=MAX_SCALE).contains(&n) => scale = n,
+                _ => return error(400, &format!("Invalid scale. Use 1 to {}.", MAX_SCALE)),
+            },
+            _ => return error(400, &format!("Unknown parameter '{}'.", key)),
+        }
+    }
+
+    let Some(data) = data else {
+        return error(400, "Missing data parameter.");
+    };
+    let qr: QrCode = match segment::encode_text(&data, ecc, true) {
+        Ok(qr) => qr,
+        Err(e) => return error(413, &format!("Failed to generate QR code: {}", e)),
+    };
+
+    if svg {
+        return (200, "image/svg+xml", to_svg_string(&qr, border as f64, scale as f64, &SvgStyle::default()).into_bytes());
+    }
+    match to_png_bytes(&qr, border, scale as u32, &PngInfo::default()) {
+        Ok(png) => (200, "image/png", png),
+        Err(e) => error(500, &e),
+    }
+}
+
+
+// Decodes a URL query value: %XX escapes and '+' for spaces.
+// Returns None for broken escapes or invalid UTF-8.
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes: &[u8] = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex: &str = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
@@ -0,0 +1,389 @@
+use crate::{Cli, Color, Grid, eye_colors, svg_number};
+
+
+// Shapes for the dark modules of SVG and PNG output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Shape {
+    #[default]
+    Square,
+    Rounded,
+    Dots,
+    Diamond,
+    Squircle,
+    VerticalBars,
+    HorizontalBars,
+}
+
+impl std::str::FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(Shape::Square),
+            "rounded" => Ok(Shape::Rounded),
+            "dots" => Ok(Shape::Dots),
+            "diamond" => Ok(Shape::Diamond),
+            "squircle" => Ok(Shape::Squircle),
+            "vertical-bars" => Ok(Shape::VerticalBars),
+            "horizontal-bars" => Ok(Shape::HorizontalBars),
+            _ => Err(format!("Unknown shape: {}. Use square, rounded, dots, diamond, squircle, vertical-bars or horizontal-bars", s)),
+        }
+    }
+}
+
+
+// Shapes for the three finder patterns, the eyes of the code.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EyeShape {
+    #[default]
+    Square,
+    Rounded,
+    Circle,
+}
+
+impl EyeShape {
+    // Corner radius of a square of the eye as a fraction of its side; a half makes a circle.
+    fn roundness(self) -> f64 {
+        match self {
+            EyeShape::Square => 0.0,
+            EyeShape::Rounded => 0.3,
+            EyeShape::Circle => 0.5,
+        }
+    }
+}
+
+impl std::str::FromStr for EyeShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(EyeShape::Square),
+            "rounded" => Ok(EyeShape::Rounded),
+            "circle" => Ok(EyeShape::Circle),
+            _ => Err(format!("Unknown eye shape: {}. Use square, rounded or circle", s)),
+        }
+    }
+}
+
+
+// Styling of the finder patterns, which are then drawn as a whole, a 7x7 ring around a
+// 3x3 ball, instead of module by module. Without a color they take the dark color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Eyes {
+    pub shape: EyeShape,
+    // Colors of the top-left, top-right and bottom-left eyes, in the order of Grid::finder_positions
+    pub colors: [Option<Color>; 3],
+}
+
+impl Eyes {
+    // Returns the color of the eye whose top-left module is at the given corner.
+    pub fn color(&self, qr: &dyn Grid, corner: (i32, i32)) -> Option<Color> {
+        qr.finder_positions().into_iter().zip(self.colors)
+            .find(|&(position, _)| position == corner)
+            .and_then(|(_, color)| color)
+    }
+
+    // Returns the SVG path data of the eye whose top-left corner is at (left, top), for
+    // the even-odd fill rule: the outer square, the hole inside it and the ball.
+    pub fn svg_path(&self, left: f64, top: f64, scale: f64) -> String {
+        [(0.0, 7.0), (1.0, 5.0), (2.0, 3.0)]
+            .map(|(inset, side)| rounded_rect_path(left + inset * scale, top + inset * scale, side * scale, side * scale, side * scale * self.shape.roundness()))
+            .join("")
+    }
+
+    // Returns whether the point (u, v) of the eye is covered, in modules from its top-left corner.
+    pub fn covers(&self, u: f64, v: f64) -> bool {
+        let within = |inset: f64, side: f64| in_rounded_rect(u - inset, v - inset, side, side, side * self.shape.roundness());
+        (within(0.0, 7.0) && !within(1.0, 5.0)) || within(2.0, 3.0)
+    }
+}
+
+
+// Returns the SVG path of a rectangle with corners rounded by the given radius.
+pub fn rounded_rect_path(left: f64, top: f64, width: f64, height: f64, r: f64) -> String {
+    rounded_corners_path(left, top, width, height, [r; 4])
+}
+
+
+// Returns the SVG path of a rectangle whose top-left, top-right, bottom-right and bottom-left
+// corners are rounded by the given radii, clockwise from the top edge.
+fn rounded_corners_path(left: f64, top: f64, width: f64, height: f64, radii: [f64; 4]) -> String {
+    let [top_left, top_right, bottom_right, bottom_left] = radii;
+    let (right, bottom) = (left + width, top + height);
+    // A zero radius arc would be a no-op, so it is left out
+    let arc = |r: f64, to_x: f64, to_y: f64| {
+        if r > 0.0 { format!("A{0},{0} 0 0 1 {1},{2}", svg_number(r), svg_number(to_x), svg_number(to_y)) } else { String::new() }
+    };
+    format!("M{},{}H{}{}V{}{}H{}{}V{}{}z",
+        svg_number(left + top_left), svg_number(top),
+        svg_number(right - top_right), arc(top_right, right, top + top_right),
+        svg_number(bottom - bottom_right), arc(bottom_right, right - bottom_right, bottom),
+        svg_number(left + bottom_left), arc(bottom_left, left, bottom - bottom_left),
+        svg_number(top + top_left), arc(top_left, left + top_left, top))
+}
+
+
+// Returns whether the point (u, v) lies in a rectangle from the origin with the given size and corner radius.
+pub fn in_rounded_rect(u: f64, v: f64, width: f64, height: f64, r: f64) -> bool {
+    in_rounded_corners(u, v, width, height, [r; 4])
+}
+
+
+// Returns whether the point (u, v) lies in a rectangle from the origin with the given size,
+// and its top-left, top-right, bottom-right and bottom-left corners rounded by the given radii.
+fn in_rounded_corners(u: f64, v: f64, width: f64, height: f64, radii: [f64; 4]) -> bool {
+    if !(0.0..width).contains(&u) || !(0.0..height).contains(&v) {
+        return false;
+    }
+    let [top_left, top_right, bottom_right, bottom_left] = radii;
+    // Inside a corner's square, the point must lie within its quarter circle
+    let within = |r: f64, dx: f64, dy: f64| dx >= r || dy >= r || (r - dx).hypot(r - dy) <= r;
+    within(top_left, u, v) && within(top_right, width - u, v)
+        && within(bottom_right, width - u, height - v) && within(bottom_left, u, height - v)
+}
+
+
+// How the modules of SVG and PNG output are drawn. The default draws plain squares.
+#[derive(Default)]
+pub struct ModuleStyle {
+    pub renderer: Option<Box<dyn ModuleRenderer>>,
+    pub eyes: Option<Eyes>,
+}
+
+impl ModuleStyle {
+    pub fn from_args(args: &Cli) -> Self {
+        let colors: [Option<Color>; 3] = eye_colors(args);
+        let eyes: Option<Eyes> = (args.eye_shape.is_some() || colors.iter().any(Option::is_some))
+            .then(|| Eyes { shape: args.eye_shape.unwrap_or_default(), colors });
+        ModuleStyle { renderer: renderer(args.shape, args.corner_radius, args.dot_radius), eyes }
+    }
+
+    // Returns the eye style if the module at (x, y) is part of a styled finder pattern,
+    // with the top-left module of that pattern.
+    pub fn eye_at(&self, qr: &dyn Grid, x: i32, y: i32) -> Option<(Eyes, (i32, i32))> {
+        self.eyes.zip(qr.finder_at(x, y))
+    }
+}
+
+
+// Draws the dark modules of a code in some shape, both as SVG paths and pixel by pixel.
+pub trait ModuleRenderer {
+    // Returns the SVG path data of the dark module at (x, y), which is `scale` wide
+    // and has its top-left corner at (left, top).
+    fn svg_path(&self, qr: &dyn Grid, x: i32, y: i32, left: f64, top: f64, scale: f64) -> String;
+
+    // Returns whether the point (u, v) of the dark module at (x, y) is covered,
+    // with u and v running from 0 to 1 across the module.
+    fn covers(&self, qr: &dyn Grid, x: i32, y: i32, u: f64, v: f64) -> bool;
+}
+
+
+// Returns the renderer for a shape with its radius options, given as fractions of the
+// module size. Plain squares need none, since they are drawn merged into larger rectangles.
+fn renderer(shape: Shape, corner_radius: f64, dot_radius: f64) -> Option<Box<dyn ModuleRenderer>> {
+    match shape {
+        Shape::Square => None,
+        Shape::Rounded => Some(Box::new(Rounded { radius: corner_radius })),
+        Shape::Dots => Some(Box::new(Dots { radius: dot_radius })),
+        Shape::Diamond => Some(Box::new(Diamond)),
+        Shape::Squircle => Some(Box::new(Squircle)),
+        Shape::VerticalBars => Some(Box::new(Bars { vertical: true })),
+        Shape::HorizontalBars => Some(Box::new(Bars { vertical: false })),
+    }
+}
+
+
+// Returns whether the module at (x, y) is drawn by the renderer rather than as a square.
+// The function patterns stay solid squares for every shape, which keeps finding and aligning the code reliable.
+pub fn is_shaped(qr: &dyn Grid, x: i32, y: i32) -> bool {
+    !qr.is_function_module(x, y)
+}
+
+
+// Returns whether the pixel at (px, py) of the code is dark, in an image with a quiet
+// zone of `border` modules and `scale` pixels per module. Pixels are sampled at their center.
+pub fn is_dark_pixel(qr: &dyn Grid, style: &ModuleStyle, border: i32, scale: u32, px: u32, py: u32) -> bool {
+    let x: i32 = (px / scale) as i32 - border;
+    let y: i32 = (py / scale) as i32 - border;
+    let (u, v) = (((px % scale) as f64 + 0.5) / scale as f64, ((py % scale) as f64 + 0.5) / scale as f64);
+    // Styled eyes may cover light modules of the pattern too, so they are checked first
+    if let Some((eyes, (left, top))) = style.eye_at(qr, x, y) {
+        return eyes.covers((x - left) as f64 + u, (y - top) as f64 + v);
+    }
+    if !qr.get_module(x, y) {
+        return false;
+    }
+    match &style.renderer {
+        Some(renderer) if is_shaped(qr, x, y) => renderer.covers(qr, x, y, u, v),
+        _ => true,
+    }
+}
+
+
+// Squares with rounded corners. A corner is only rounded where both neighbours beside it
+// are light, so touching modules stay joined.
+struct Rounded {
+    radius: f64,
+}
+
+impl Rounded {
+    // Radii of the top-left, top-right, bottom-right and bottom-left corners, as fractions of the module.
+    fn corners(&self, qr: &dyn Grid, x: i32, y: i32) -> [f64; 4] {
+        let light = |dx: i32, dy: i32| !qr.get_module(x + dx, y + dy);
+        let corner = |dx: i32, dy: i32| if light(dx, 0) && light(0, dy) { self.radius } else { 0.0 };
+        [corner(-1, -1), corner(1, -1), corner(1, 1), corner(-1, 1)]
+    }
+}
+
+impl ModuleRenderer for Rounded {
+    fn svg_path(&self, qr: &dyn Grid, x: i32, y: i32, left: f64, top: f64, scale: f64) -> String {
+        rounded_corners_path(left, top, scale, scale, self.corners(qr, x, y).map(|r| r * scale))
+    }
+
+    fn covers(&self, qr: &dyn Grid, x: i32, y: i32, u: f64, v: f64) -> bool {
+        in_rounded_corners(u, v, 1.0, 1.0, self.corners(qr, x, y))
+    }
+}
+
+
+// Circles in the middle of the data modules.
+struct Dots {
+    radius: f64,
+}
+
+impl ModuleRenderer for Dots {
+    fn svg_path(&self, _qr: &dyn Grid, _x: i32, _y: i32, left: f64, top: f64, scale: f64) -> String {
+        // Two half circles, from the left end of the horizontal diameter and back
+        let r: f64 = self.radius * scale;
+        format!("M{},{}a{2},{2} 0 1 0 {3},0a{2},{2} 0 1 0 -{3},0z",
+            svg_number(left + scale / 2.0 - r), svg_number(top + scale / 2.0), svg_number(r), svg_number(r * 2.0))
+    }
+
+    fn covers(&self, _qr: &dyn Grid, _x: i32, _y: i32, u: f64, v: f64) -> bool {
+        (u - 0.5).hypot(v - 0.5) <= self.radius
+    }
+}
+
+
+// Squares turned by 45 degrees, with their corners at the middle of the module's edges.
+struct Diamond;
+
+impl ModuleRenderer for Diamond {
+    fn svg_path(&self, _qr: &dyn Grid, _x: i32, _y: i32, left: f64, top: f64, scale: f64) -> String {
+        let half: f64 = scale / 2.0;
+        // Clockwise from the top corner
+        format!("M{},{}l{2},{2}l-{2},{2}l-{2},-{2}z", svg_number(left + half), svg_number(top), svg_number(half))
+    }
+
+    fn covers(&self, _qr: &dyn Grid, _x: i32, _y: i32, u: f64, v: f64) -> bool {
+        (u - 0.5).abs() + (v - 0.5).abs() <= 0.5
+    }
+}
+
+
+// Superellipses, between a circle and a square: |x|^4 + |y|^4 <= 1 across the module.
+struct Squircle;
+
+// Where the control points of each quarter's cubic curve sit, as a share of the half width
+// from the center. It puts the curve's middle on the superellipse.
+const SQUIRCLE_CONTROL: f64 = 0.91;
+
+impl ModuleRenderer for Squircle {
+    fn svg_path(&self, _qr: &dyn Grid, _x: i32, _y: i32, left: f64, top: f64, scale: f64) -> String {
+        let half: f64 = scale / 2.0;
+        let (cx, cy) = (left + half, top + half);
+        let c: f64 = half * SQUIRCLE_CONTROL;
+        // Clockwise from the top, one cubic curve per quarter
+        let corners: [(f64, f64); 4] = [(1.0, -1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)];
+        let mut path: String = format!("M{},{}", svg_number(cx), svg_number(top));
+        for (i, (sx, sy)) in corners.into_iter().enumerate() {
+            // Even quarters leave a vertical extreme for a horizontal one, odd quarters the other way
+            let (from, to) = if i % 2 == 0 { ((0.0, sy), (sx, 0.0)) } else { ((sx, 0.0), (0.0, sy)) };
+            let control = |(px, py): (f64, f64)| (if px == 0.0 { sx * c } else { px * half }, if py == 0.0 { sy * c } else { py * half });
+            let ((x1, y1), (x2, y2)) = (control(from), control(to));
+            path += &format!("C{},{} {},{} {},{}", svg_number(cx + x1), svg_number(cy + y1), svg_number(cx + x2), svg_number(cy + y2),
+                svg_number(cx + to.0 * half), svg_number(cy + to.1 * half));
+        }
+        path + "z"
+    }
+
+    fn covers(&self, _qr: &dyn Grid, _x: i32, _y: i32, u: f64, v: f64) -> bool {
+        (2.0 * u - 1.0).powi(4) + (2.0 * v - 1.0).powi(4) <= 1.0
+    }
+}
+
+
+// Width of a bar as a share of the module.
+const BAR_WIDTH: f64 = 0.8;
+
+// Bars running down the columns or along the rows. Dark modules next to each other in the
+// bar's direction join up; the ends of a bar are rounded.
+struct Bars {
+    vertical: bool,
+}
+
+impl Bars {
+    // Radii of the top-left, top-right, bottom-right and bottom-left corners of the module's part
+    // of the bar, as fractions of the module: ends without a dark neighbour are rounded off.
+    fn corners(&self, qr: &dyn Grid, x: i32, y: i32) -> [f64; 4] {
+        let r: f64 = BAR_WIDTH / 2.0;
+        let end = |dx: i32, dy: i32| if qr.get_module(x + dx, y + dy) { 0.0 } else { r };
+        if self.vertical {
+            let (top, bottom) = (end(0, -1), end(0, 1));
+            [top, top, bottom, bottom]
+        } else {
+            let (left, right) = (end(-1, 0), end(1, 0));
+            [left, right, right, left]
+        }
+    }
+
+    // Returns the offset and size of the bar within the module, as fractions of it.
+    fn extent(&self) -> (f64, f64, f64, f64) {
+        let inset: f64 = (1.0 - BAR_WIDTH) / 2.0;
+        if self.vertical { (inset, 0.0, BAR_WIDTH, 1.0) } else { (0.0, inset, 1.0, BAR_WIDTH) }
+    }
+}
+
+impl ModuleRenderer for Bars {
+    fn svg_path(&self, qr: &dyn Grid, x: i32, y: i32, left: f64, top: f64, scale: f64) -> String {
+        let (dx, dy, width, height) = self.extent();
+        rounded_corners_path(left + dx * scale, top + dy * scale, width * scale, height * scale, self.corners(qr, x, y).map(|r| r * scale))
+    }
+
+    fn covers(&self, qr: &dyn Grid, x: i32, y: i32, u: f64, v: f64) -> bool {
+        let (dx, dy, width, height) = self.extent();
+        in_rounded_corners(u - dx, v - dy, width, height, self.corners(qr, x, y))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcodegen::{QrCode, QrCodeEcc, QrSegment, Version};
+
+    // Version 7 has six alignment patterns and the version information, so a shape that
+    // wrongly styled any function pattern would break the code.
+    #[test]
+    fn shaped_version_7_codes_decode() {
+        let text: &str = "https://example.com/shapes";
+        let segs: Vec<QrSegment> = QrSegment::make_segments(text);
+        let qr: QrCode = QrCode::encode_segments_advanced(&segs, QrCodeEcc::Medium, Version::new(7), Version::new(40), None, true).unwrap();
+        assert_eq!(qr.version().value(), 7);
+        let (border, scale) = (4, 8);
+        let width: u32 = (qr.size() + 2 * border) as u32 * scale;
+        for shape in [Shape::Square, Shape::Rounded, Shape::Dots, Shape::Diamond, Shape::Squircle, Shape::VerticalBars, Shape::HorizontalBars] {
+            let style = ModuleStyle { renderer: renderer(shape, 0.5, 0.45), eyes: None };
+            let image = image::GrayImage::from_fn(width, width, |px, py| {
+                image::Luma([if is_dark_pixel(&qr, &style, border, scale, px, py) { 0 } else { 255 }])
+            });
+            let mut decoder = quircs::Quirc::default();
+            let payloads: Vec<Vec<u8>> = decoder
+                .identify(width as usize, width as usize, &image)
+                .filter_map(|code| code.ok()?.decode().ok())
+                .map(|data| data.payload)
+                .collect();
+            assert_eq!(payloads, [text.as_bytes()], "{:?} modules", shape);
+        }
+    }
+}
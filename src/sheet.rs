@@ -0,0 +1,347 @@
+use image::{GrayImage, Luma, imageops};
+use qrcodegen::{QrCode, QrCodeEcc};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::{Cli, PageSize, check_overwrite, parse_ecc_levels, parse_physical_size, pdf, raster_size, segment, suffixed_path, svg_number, to_luma_image};
+
+// Layout units of PDF sheets, fine enough that rounding to them doesn't shift the cells.
+const PDF_UNITS_PER_POINT: f64 = 100.0;
+
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SheetArgs {
+    #[arg(value_name = "PAYLOADS", help = "File with one payload per line, or a CSV file whose first column holds the payloads. Use - for stdin.")]
+    payloads: PathBuf,
+
+    #[arg(short = 'o', long, value_name = "OUTPUT_FILE", help = "PNG or PDF file for the sheet. A PDF holds every page; further PNG pages get numbered files.", default_value = "sheet.png")]
+    output_file: PathBuf,
+
+    #[arg(long, value_name = "PAGE", help = "Page size. Use A4 or Letter.", default_value = "A4")]
+    page: PageSize,
+
+    #[arg(long, value_name = "SIZE", value_parser = parse_physical_size, help = "Printed size of each code including border, e.g. 30mm.", default_value = "30mm")]
+    size: f64,
+
+    #[arg(long, value_name = "MARGIN", value_parser = parse_physical_size, help = "Blank margin around the page.", default_value = "10mm")]
+    margin: f64,
+
+    #[arg(long, value_name = "GAP", value_parser = parse_physical_size, help = "Space between neighbouring codes.", default_value = "5mm")]
+    gap: f64,
+
+    #[arg(long, value_name = "DPI", help = "Print resolution in dots per inch.", default_value_t = 300.0)]
+    dpi: f64,
+
+    #[arg(long, value_name = "COLUMNS", help = "Number of columns per page. Defaults to as many as fit.")]
+    columns: Option<u32>,
+
+    #[arg(long, value_name = "ROWS", help = "Number of rows per page. Defaults to as many as fit.")]
+    rows: Option<u32>,
+
+    #[arg(long, help = "Draw crop marks in the page margin where the codes are to be cut apart.")]
+    cut_marks: bool,
+
+    #[arg(long, help = "Print each payload under its code. PDF only.")]
+    captions: bool,
+
+    #[arg(long, value_name = "POINTS", help = "Largest font size of the captions in points. Long captions are set smaller to fit.", default_value_t = 8.0)]
+    caption_size: f64,
+}
+
+
+// Where the codes go on a page, in pixels for PNG or hundredths of a point for PDF.
+struct Layout {
+    page_width: u32,
+    page_height: u32,
+    margin: u32,
+    cell: u32,
+    pitch: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl Layout {
+    // Fits as many cells as possible between the margins, or the requested number of
+    // columns and rows, the grid centered on the page.
+    fn new(sheet: &SheetArgs, units_per_inch: f64) -> Result<Self, String> {
+        let units = |inches: f64| (inches * units_per_inch).round() as u32;
+        let (width, height) = sheet.page.inches();
+        let (page_width, page_height) = (units(width), units(height));
+        let (margin, cell, gap) = (units(sheet.margin), units(sheet.size), units(sheet.gap));
+
+        let fit = |length: u32| (length.saturating_sub(2 * margin) + gap) / (cell + gap);
+        let (columns, rows) = (fit(page_width), fit(page_height));
+        if columns == 0 || rows == 0 {
+            return Err(format!("A {:.1}mm code does not fit between the margins of {:?} paper.", sheet.size * 25.4, sheet.page));
+        }
+        if sheet.columns.is_some_and(|wanted| wanted > columns) || sheet.rows.is_some_and(|wanted| wanted > rows) {
+            return Err(format!("Only {} x {} codes of {:.1}mm fit between the margins of {:?} paper.", columns, rows, sheet.size * 25.4, sheet.page));
+        }
+        let (columns, rows) = (sheet.columns.unwrap_or(columns), sheet.rows.unwrap_or(rows));
+        Ok(Layout { page_width, page_height, margin, cell, pitch: cell + gap, columns, rows })
+    }
+
+    fn per_page(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    // Top-left corner of the grid, which is centered between the margins.
+    fn origin(&self) -> (u32, u32) {
+        let grid_width: u32 = self.columns * self.pitch - (self.pitch - self.cell);
+        let grid_height: u32 = self.rows * self.pitch - (self.pitch - self.cell);
+        ((self.page_width - grid_width) / 2, (self.page_height - grid_height) / 2)
+    }
+
+    // Top-left corner of the i-th cell on a page.
+    fn cell_origin(&self, i: usize) -> (u32, u32) {
+        let (left, top) = self.origin();
+        (left + (i as u32 % self.columns) * self.pitch, top + (i as u32 / self.columns) * self.pitch)
+    }
+
+    // Short lines in the page margin in line with every cell edge, as (x, y, width, height),
+    // so the sheet can be cut apart without marks reaching into the codes.
+    fn cut_marks(&self) -> Vec<(u32, u32, u32, u32)> {
+        let (left, top) = self.origin();
+        let length: u32 = self.margin * 2 / 3;
+        let thickness: u32 = (self.page_width / 800).max(1);
+        let mut marks: Vec<(u32, u32, u32, u32)> = Vec::new();
+        for column in 0..self.columns {
+            for edge in [left + column * self.pitch, left + column * self.pitch + self.cell] {
+                marks.push((edge, 0, thickness, length));
+                marks.push((edge, self.page_height - length, thickness, length));
+            }
+        }
+        for row in 0..self.rows {
+            for edge in [top + row * self.pitch, top + row * self.pitch + self.cell] {
+                marks.push((0, edge, length, thickness));
+                marks.push((self.page_width - length, edge, length, thickness));
+            }
+        }
+        marks
+    }
+}
+
+
+// Renders every payload into a grid of codes on as many pages as needed
+// and writes them as one PDF document or one PNG file per page.
+pub fn run(args: &Cli, sheet: &SheetArgs) -> io::Result<()> {
+    let ecc: QrCodeEcc = match parse_ecc_levels(&args.ecc).as_deref() {
+        Some([ecc]) => *ecc,
+        _ => {
+            eprintln!("A sheet uses a single error correction level. Use L, M, Q, or H.");
+            std::process::exit(1);
+        }
+    };
+    if sheet.dpi <= 0.0 {
+        eprintln!("The resolution must be positive.");
+        std::process::exit(1);
+    }
+    let (border, _) = raster_size(args.border_width, 1.0);
+
+    let payloads: Vec<String> = read_payloads(&sheet.payloads)?;
+    if payloads.is_empty() {
+        eprintln!("No payloads found in '{}'. Nothing to encode.", sheet.payloads.display());
+        std::process::exit(1);
+    }
+
+    let is_pdf: bool = sheet.output_file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    if sheet.captions && !is_pdf {
+        eprintln!("Warning: --captions are only printed on PDF sheets.");
+    }
+    if sheet.caption_size <= 0.0 {
+        eprintln!("The caption size must be positive.");
+        std::process::exit(1);
+    }
+
+    let units_per_inch: f64 = if is_pdf { pdf::POINTS_PER_INCH * PDF_UNITS_PER_POINT } else { sheet.dpi };
+    let layout = match Layout::new(sheet, units_per_inch) {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let pages: Vec<&[String]> = payloads.chunks(layout.per_page()).collect();
+    if args.verbose {
+        eprintln!("{} x {} codes per page, {} page{}", layout.columns, layout.rows, pages.len(), if pages.len() == 1 { "" } else { "s" });
+    }
+
+    if is_pdf {
+        let document: Vec<u8> = pages
+            .iter()
+            .map(|page| pdf_page(page, &layout, ecc, !args.no_optimize, border as f64, sheet))
+            .collect::<Result<Vec<(f64, f64, String)>, String>>()
+            .map(|pages| pdf::document(&pages))
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+        if let Err(e) = check_overwrite(&sheet.output_file, args.force) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return std::fs::write(&sheet.output_file, document);
+    }
+
+    for (number, page) in pages.iter().enumerate() {
+        let path: PathBuf = if pages.len() > 1 {
+            suffixed_path(&sheet.output_file, &(number + 1).to_string())
+        } else {
+            sheet.output_file.clone()
+        };
+        let image: GrayImage = render_page(page, &layout, ecc, !args.no_optimize, border, sheet.cut_marks).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if let Err(e) = check_overwrite(&path, args.force) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        if let Err(e) = image.save(&path) {
+            eprintln!("Failed to save sheet '{}': {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+
+// Draws one page of codes, row by row, each code centered in its cell.
+fn render_page(payloads: &[String], layout: &Layout, ecc: QrCodeEcc, optimize: bool, border: i32, cut_marks: bool) -> Result<GrayImage, String> {
+    let mut page: GrayImage = GrayImage::from_pixel(layout.page_width, layout.page_height, Luma([255u8]));
+
+    for (i, payload) in payloads.iter().enumerate() {
+        let qr: QrCode = encode(payload, ecc, optimize)?;
+        let modules: u32 = (qr.size() + 2 * border) as u32;
+        let scale: u32 = layout.cell / modules;
+        if scale == 0 {
+            return Err(format!("'{}' needs {} modules, more than the {} pixels of a cell. Increase --size or --dpi.", payload, modules, layout.cell));
+        }
+
+        let code: GrayImage = to_luma_image(&qr, border, scale, false)?;
+        let offset: u32 = (layout.cell - code.width()) / 2;
+        let (x, y) = layout.cell_origin(i);
+        imageops::replace(&mut page, &code, (x + offset) as i64, (y + offset) as i64);
+    }
+
+    if cut_marks {
+        for (x, y, width, height) in layout.cut_marks() {
+            for py in y..(y + height).min(page.height()) {
+                for px in x..(x + width).min(page.width()) {
+                    page.put_pixel(px, py, Luma([0u8]));
+                }
+            }
+        }
+    }
+    Ok(page)
+}
+
+
+// Returns one page of the PDF sheet as width, height and content stream. Codes are vector
+// drawings filling their cell, or the space above the caption when captions are printed.
+fn pdf_page(payloads: &[String], layout: &Layout, ecc: QrCodeEcc, optimize: bool, border: f64, sheet: &SheetArgs) -> Result<(f64, f64, String), String> {
+    let points = |units: u32| units as f64 / PDF_UNITS_PER_POINT;
+    let (page_width, page_height, cell) = (points(layout.page_width), points(layout.page_height), points(layout.cell));
+    let caption_band: f64 = if sheet.captions { sheet.caption_size * 1.5 } else { 0.0 };
+    let side: f64 = cell - caption_band;
+    let mut content = String::new();
+
+    for (i, payload) in payloads.iter().enumerate() {
+        let qr: QrCode = encode(payload, ecc, optimize)?;
+        let (x, y) = layout.cell_origin(i);
+        let (left, top) = (points(x), points(y));
+        let scale: f64 = side / (qr.size() as f64 + border * 2.0);
+        content += &pdf::code_content(&qr, border, scale, left + (cell - side) / 2.0, top, page_height, false);
+        if sheet.captions {
+            content += &caption_content(payload, left, top + side, cell, sheet.caption_size, page_height);
+        }
+    }
+
+    if sheet.cut_marks {
+        for (x, y, width, height) in layout.cut_marks() {
+            content += &format!("{} {} {} {} re\n", svg_number(points(x)), svg_number(page_height - points(y + height)),
+                svg_number(points(width)), svg_number(points(height)));
+        }
+        content += "f\n";
+    }
+    Ok((page_width, page_height, content))
+}
+
+
+// Returns the operators that center a caption in a band of the given width whose top is at
+// `top`, shrinking the font when the text would be wider than the band.
+fn caption_content(text: &str, left: f64, top: f64, width: f64, max_size: f64, page_height: f64) -> String {
+    // Widths of the Helvetica glyphs in thousandths of the font size
+    let text_width: f64 = text.chars().map(helvetica_width).sum::<f64>() / 1000.0;
+    let size: f64 = if text_width > 0.0 { max_size.min(width / text_width) } else { max_size };
+    let x: f64 = left + (width - text_width * size) / 2.0;
+    let baseline: f64 = page_height - top - size * 1.1;
+    format!("BT /F1 {} Tf {} {} Td ({}) Tj ET\n", svg_number(size), svg_number(x), svg_number(baseline), pdf::escape_text(text))
+}
+
+
+// Returns the advance width of a character in the standard Helvetica font.
+// Characters outside ASCII are printed as '?'.
+fn helvetica_width(c: char) -> f64 {
+    const WIDTHS: [u16; 95] = [
+        278, 278, 355, 556, 556, 889, 667, 222, 333, 333, 389, 584, 278, 333, 278, 278,
+        556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+        1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+        667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+        222, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+        556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+    ];
+    match c {
+        ' '..='~' => WIDTHS[c as usize - 32] as f64,
+        _ => WIDTHS['?' as usize - 32] as f64,
+    }
+}
+
+
+// Encodes one payload of the sheet.
+fn encode(payload: &str, ecc: QrCodeEcc, optimize: bool) -> Result<QrCode, String> {
+    segment::encode_text(payload, ecc, optimize).map_err(|e| format!("Failed to generate QR code for '{}': {}", payload, e))
+}
+
+
+// Reads the payloads, one per non-empty line. CSV files contribute the first field of each row.
+fn read_payloads(path: &Path) -> io::Result<Vec<String>> {
+    let mut content = String::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_string(&mut content)?;
+    } else {
+        content = std::fs::read_to_string(path).map_err(|e| {
+            eprintln!("Error reading file '{}': {}", path.display(), e);
+            e
+        })?;
+    }
+
+    let is_csv: bool = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    Ok(content
+        .lines()
+        .map(|line| if is_csv { first_csv_field(line) } else { line.to_string() })
+        .filter(|payload| !payload.is_empty())
+        .collect())
+}
+
+
+// Returns the first field of a CSV row, unquoting it if needed.
+fn first_csv_field(line: &str) -> String {
+    let Some(quoted) = line.strip_prefix('"') else {
+        return line.split(',').next().unwrap_or_default().to_string();
+    };
+    let mut field = String::new();
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => break,
+            c => field.push(c),
+        }
+    }
+    field
+}
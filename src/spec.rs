@@ -0,0 +1,316 @@
+use qrcodegen::{QrCodeEcc, QrSegment, QrSegmentMode};
+
+
+// Number of error correction codewords per block, indexed by [ecc][version].
+// Index 0 is padding. Values are from the QR Code specification (ISO/IEC 18004, table 9).
+const ECC_CODEWORDS_PER_BLOCK: [[i8; 41]; 4] = [
+    [-1,  7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],  // Low
+    [-1, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],  // Medium
+    [-1, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],  // Quartile
+    [-1, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],  // High
+];
+
+// Number of error correction blocks, indexed by [ecc][version].
+const NUM_ERROR_CORRECTION_BLOCKS: [[i8; 41]; 4] = [
+    [-1, 1, 1, 1, 1, 1, 2, 2, 2, 2,  4,  4,  4,  4,  4,  6,  6,  6,  6,  7,  8,  8,  9,  9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],  // Low
+    [-1, 1, 1, 1, 2, 2, 4, 4, 4, 5,  5,  5,  8,  9,  9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],  // Medium
+    [-1, 1, 1, 2, 2, 4, 4, 6, 6, 8,  8,  8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],  // Quartile
+    [-1, 1, 1, 2, 4, 4, 4, 5, 6, 8,  8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],  // High
+];
+
+
+// Row index of the given error correction level in the tables above.
+fn ecc_index(ecl: QrCodeEcc) -> usize {
+    match ecl {
+        QrCodeEcc::Low => 0,
+        QrCodeEcc::Medium => 1,
+        QrCodeEcc::Quartile => 2,
+        QrCodeEcc::High => 3,
+    }
+}
+
+
+// Returns the number of modules available for data (including remainder bits)
+// after all function patterns of the given version have been placed.
+pub fn num_raw_data_modules(version: u8) -> usize {
+    let ver = version as usize;
+    let mut result: usize = (16 * ver + 128) * ver + 64;
+    if ver >= 2 {
+        let num_align: usize = ver / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if ver >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+
+// Returns the number of error correction blocks for the given version and level.
+pub fn num_ec_blocks(version: u8, ecl: QrCodeEcc) -> usize {
+    NUM_ERROR_CORRECTION_BLOCKS[ecc_index(ecl)][version as usize] as usize
+}
+
+
+// Returns the total number of error correction codewords for the given version and level.
+pub fn num_ec_codewords(version: u8, ecl: QrCodeEcc) -> usize {
+    ECC_CODEWORDS_PER_BLOCK[ecc_index(ecl)][version as usize] as usize * num_ec_blocks(version, ecl)
+}
+
+
+// Returns the number of 8-bit data codewords for the given version and level.
+pub fn num_data_codewords(version: u8, ecl: QrCodeEcc) -> usize {
+    num_raw_data_modules(version) / 8 - num_ec_codewords(version, ecl)
+}
+
+
+// Returns the number of remainder bits left over after the last codeword.
+pub fn num_remainder_bits(version: u8) -> usize {
+    num_raw_data_modules(version) % 8
+}
+
+
+// Returns the number of codeword errors each error correction block can correct. The smallest
+// versions spend some error correction codewords on misdecode protection instead (table 9).
+pub fn correctable_codewords(version: u8, ecl: QrCodeEcc) -> usize {
+    let misdecode: usize = match (version, ecl) {
+        (1, QrCodeEcc::Low) => 3,
+        (1, QrCodeEcc::Medium) | (2, QrCodeEcc::Low) => 2,
+        (1, _) | (3, QrCodeEcc::Low) => 1,
+        _ => 0,
+    };
+    (ECC_CODEWORDS_PER_BLOCK[ecc_index(ecl)][version as usize] as usize - misdecode) / 2
+}
+
+
+// Returns the error correction block each codeword belongs to, in the interleaved order the
+// codewords are placed in: the data codewords of all blocks in turn, then the error correction
+// codewords. Blocks are numbered from 0; the short blocks come first and lack the last data codeword.
+pub fn interleaved_blocks(version: u8, ecl: QrCodeEcc) -> Vec<usize> {
+    let num_blocks: usize = num_ec_blocks(version, ecl);
+    let block_ecc_len: usize = ECC_CODEWORDS_PER_BLOCK[ecc_index(ecl)][version as usize] as usize;
+    let raw_codewords: usize = num_raw_data_modules(version) / 8;
+    let num_short_blocks: usize = num_blocks - raw_codewords % num_blocks;
+    let short_block_len: usize = raw_codewords / num_blocks;
+
+    let mut result: Vec<usize> = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_block_len {
+        for block in 0..num_blocks {
+            if i != short_block_len - block_ecc_len || block >= num_short_blocks {
+                result.push(block);
+            }
+        }
+    }
+    result
+}
+
+
+// Returns the data modules in the order the codeword bits are placed: in pairs of columns
+// from the right edge, zigzagging up and down, skipping the vertical timing pattern.
+// The trailing remainder bits belong to no codeword.
+pub fn placement_order(version: u8) -> Vec<(i32, i32)> {
+    let size: i32 = version as i32 * 4 + 17;
+    let mut result: Vec<(i32, i32)> = Vec::with_capacity(num_raw_data_modules(version));
+    let mut right: i32 = size - 1;
+    while right >= 1 {
+        if right == 6 {
+            right = 5;
+        }
+        let upward: bool = (right + 1) & 2 == 0;
+        for vertical in 0..size {
+            let y: i32 = if upward { size - 1 - vertical } else { vertical };
+            for x in [right, right - 1] {
+                if module_kind(version, x, y) == ModuleKind::Data {
+                    result.push((x, y));
+                }
+            }
+        }
+        right -= 2;
+    }
+    result
+}
+
+
+// Returns the single-letter name of an error correction level.
+pub fn ecc_letter(ecl: QrCodeEcc) -> char {
+    match ecl {
+        QrCodeEcc::Low => 'L',
+        QrCodeEcc::Medium => 'M',
+        QrCodeEcc::Quartile => 'Q',
+        QrCodeEcc::High => 'H',
+    }
+}
+
+
+// Returns the width of the character count field of a segment mode at the given version.
+pub fn char_count_bits(mode: QrSegmentMode, version: u8) -> usize {
+    let range: usize = match version {
+        1..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+    match mode {
+        QrSegmentMode::Numeric => [10, 12, 14][range],
+        QrSegmentMode::Alphanumeric => [9, 11, 13][range],
+        QrSegmentMode::Byte => [8, 16, 16][range],
+        QrSegmentMode::Kanji => [8, 10, 12][range],
+        QrSegmentMode::Eci => 0,
+    }
+}
+
+
+// Returns the number of bits a segment takes at the given version:
+// the 4-bit mode indicator, the character count field and the data.
+pub fn segment_bits(seg: &QrSegment, version: u8) -> usize {
+    4 + char_count_bits(seg.mode(), version) + seg.data().len()
+}
+
+
+// What a module of the symbol is used for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModuleKind {
+    Finder,
+    Separator,
+    Timing,
+    Alignment,
+    Format,
+    Version,
+    DarkModule,
+    Data,
+}
+
+
+// Returns the center coordinates of the alignment patterns, in ascending order,
+// computed the same way as qrcodegen. Version 1 has none.
+pub fn alignment_positions(version: u8) -> Vec<i32> {
+    let ver = version as i32;
+    if ver == 1 {
+        return Vec::new();
+    }
+    let size: i32 = ver * 4 + 17;
+    let num_align: i32 = ver / 7 + 2;
+    let step: i32 = (ver * 8 + num_align * 3 + 5) / (num_align * 4 - 4) * 2;
+    let mut result: Vec<i32> = (0..num_align - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+
+// Returns the top-left modules of the three 7x7 finder patterns: top left, top right and bottom left.
+pub fn finder_positions(version: u8) -> [(i32, i32); 3] {
+    let size: i32 = version as i32 * 4 + 17;
+    [(0, 0), (size - 7, 0), (0, size - 7)]
+}
+
+
+// Returns the top-left module of the finder pattern that the module at (x, y) belongs to.
+pub fn finder_at(version: u8, x: i32, y: i32) -> Option<(i32, i32)> {
+    finder_positions(version).into_iter().find(|&(left, top)| (left..left + 7).contains(&x) && (top..top + 7).contains(&y))
+}
+
+
+// Classifies the module at (x, y) of a symbol of the given version.
+pub fn module_kind(version: u8, x: i32, y: i32) -> ModuleKind {
+    let size: i32 = version as i32 * 4 + 17;
+
+    // The three finder corners, each with a 1-module separator towards the symbol
+    for (left, top) in finder_positions(version) {
+        if (left..left + 7).contains(&x) && (top..top + 7).contains(&y) {
+            return ModuleKind::Finder;
+        }
+        if (left - 1..left + 8).contains(&x) && (top - 1..top + 8).contains(&y) {
+            return ModuleKind::Separator;
+        }
+    }
+    if x == 8 && y == size - 8 {
+        return ModuleKind::DarkModule;
+    }
+
+    // Alignment patterns are skipped where they would overlap a finder and drawn over the timing lines
+    let positions: Vec<i32> = alignment_positions(version);
+    let last: usize = positions.len().saturating_sub(1);
+    for (i, &cx) in positions.iter().enumerate() {
+        for (j, &cy) in positions.iter().enumerate() {
+            let overlaps_finder: bool = (i == 0 && (j == 0 || j == last)) || (i == last && j == 0);
+            if !overlaps_finder && (x - cx).abs() <= 2 && (y - cy).abs() <= 2 {
+                return ModuleKind::Alignment;
+            }
+        }
+    }
+
+    if x == 6 || y == 6 {
+        return ModuleKind::Timing;
+    }
+    if (y == 8 && (x <= 8 || x >= size - 8)) || (x == 8 && (y <= 8 || y >= size - 8)) {
+        return ModuleKind::Format;
+    }
+    if version >= 7 && ((x >= size - 11 && y < 6) || (y >= size - 11 && x < 6)) {
+        return ModuleKind::Version;
+    }
+
+    ModuleKind::Data
+}
+
+
+// Returns whether the module at (x, y) belongs to a function pattern or the format and
+// version information, which module stylers must leave as solid squares. Alignment patterns
+// exist from version 2 on, so this depends on the version and not just on the finders.
+pub fn is_functional_module(x: i32, y: i32, version: u8) -> bool {
+    module_kind(version, x, y) != ModuleKind::Data
+}
+
+
+// Returns the maximum number of characters one segment of the given mode can hold
+// in a code of the given version and error correction level.
+pub fn max_chars(mode: QrSegmentMode, version: u8, ecl: QrCodeEcc) -> usize {
+    let available: usize = (num_data_codewords(version, ecl) * 8).saturating_sub(4 + char_count_bits(mode, version));
+    match mode {
+        // 10 bits per 3 digits, 7 bits for 2 and 4 bits for 1 leftover digit
+        QrSegmentMode::Numeric => available / 10 * 3 + match available % 10 {
+            7.. => 2,
+            4.. => 1,
+            _ => 0,
+        },
+        // 11 bits per 2 characters, 6 bits for a leftover one
+        QrSegmentMode::Alphanumeric => available / 11 * 2 + usize::from(available % 11 >= 6),
+        QrSegmentMode::Byte => available / 8,
+        QrSegmentMode::Kanji => available / 13,
+        QrSegmentMode::Eci => 0,
+    }
+}
+
+
+// Parses the name of a data encoding mode.
+pub fn parse_mode(name: &str) -> Result<QrSegmentMode, String> {
+    match name.to_lowercase().as_str() {
+        "numeric" => Ok(QrSegmentMode::Numeric),
+        "alphanumeric" => Ok(QrSegmentMode::Alphanumeric),
+        "byte" => Ok(QrSegmentMode::Byte),
+        "kanji" => Ok(QrSegmentMode::Kanji),
+        _ => Err(format!("Unknown mode: {}. Use numeric, alphanumeric, byte or kanji", name)),
+    }
+}
+
+
+// Appends the lowest `width` bits of the value, most significant first.
+pub fn append_bits(bits: &mut Vec<bool>, value: u32, width: usize) {
+    bits.extend((0..width).rev().map(|i| value >> i & 1 != 0));
+}
+
+
+// Packs the data bits into `capacity` codewords: up to `terminator` zero bits end the data
+// (4 in QR codes), zeros fill up the last byte and the pad bytes 0xEC and 0x11 take turns after it.
+pub fn pad_codewords(mut bits: Vec<bool>, terminator: usize, capacity: usize) -> Vec<u8> {
+    let terminator: usize = (capacity * 8 - bits.len()).min(terminator);
+    append_bits(&mut bits, 0, terminator);
+    let filler: usize = (8 - bits.len() % 8) % 8;
+    append_bits(&mut bits, 0, filler);
+
+    let mut codewords: Vec<u8> = bits.chunks(8)
+        .map(|byte| byte.iter().fold(0, |value, &bit| value << 1 | bit as u8))
+        .collect();
+    let padding: usize = capacity - codewords.len();
+    codewords.extend([0xEC, 0x11].into_iter().cycle().take(padding));
+    codewords
+}
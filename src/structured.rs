@@ -0,0 +1,81 @@
+use qrcodegen::{Mask, QrCode, QrCodeEcc, QrSegmentMode, Version};
+
+use crate::spec;
+
+// Most symbols in a sequence, as the symbol index and count have 4 bits each.
+const MAX_SYMBOLS: usize = 16;
+
+// Bits of the structured append header: the mode indicator, symbol index, symbol count and parity byte.
+const HEADER_BITS: usize = 4 + 4 + 4 + 8;
+
+
+// Splits data over the fewest symbols up to version `max` that hold it, all at the same version
+// and level, in byte mode behind a structured append header so readers can join the parts again.
+// Text is only cut between characters. Returns each symbol with its part of the data.
+pub fn encode(data: &[u8], text: bool, ecc: QrCodeEcc, min: Version, max: Version, mask: Option<Mask>, boost_ecc: bool)
+    -> Result<Vec<(QrCode, Vec<u8>)>, String> {
+    // The parity byte is the same in every symbol, so a reader can tell which ones belong together
+    let parity: u8 = data.iter().fold(0, |parity, &b| parity ^ b);
+    for count in 1..=MAX_SYMBOLS {
+        let parts: Vec<&[u8]> = split(data, count, text);
+        let largest: usize = parts.iter().map(|part| part.len()).max().unwrap_or(0);
+        let Some(version) = (min.value()..=max.value()).find(|&version| fits(largest, version, ecc)) else { continue };
+
+        // Like qrcodegen, raise the level as far as the version still holds the data
+        let mut ecc: QrCodeEcc = ecc;
+        for higher in [QrCodeEcc::Medium, QrCodeEcc::Quartile, QrCodeEcc::High] {
+            if boost_ecc && higher > ecc && fits(largest, version, higher) {
+                ecc = higher;
+            }
+        }
+        return Ok(parts.iter().enumerate()
+            .map(|(index, part)| {
+                let codewords: Vec<u8> = codewords(part, index, count, parity, version, ecc);
+                (QrCode::encode_codewords(Version::new(version), ecc, &codewords, mask), part.to_vec())
+            })
+            .collect());
+    }
+    Err(format!("The data does not fit in {} symbols up to version {} at ECC {}.", MAX_SYMBOLS, max.value(), spec::ecc_letter(ecc)))
+}
+
+
+// Cuts data into `count` parts of about the same size. Text parts end at the next character boundary.
+fn split(data: &[u8], count: usize, text: bool) -> Vec<&[u8]> {
+    let mut parts: Vec<&[u8]> = Vec::with_capacity(count);
+    let mut start: usize = 0;
+    for i in 1..=count {
+        let mut end: usize = (data.len() * i).div_ceil(count).max(start);
+        // UTF-8 continuation bytes start with the bits 10
+        while text && end < data.len() && data[end] & 0xC0 == 0x80 {
+            end += 1;
+        }
+        parts.push(&data[start..end]);
+        start = end;
+    }
+    parts
+}
+
+
+// Returns whether a part of `length` bytes fits in a symbol of the given version and level with its header.
+fn fits(length: usize, version: u8, ecc: QrCodeEcc) -> bool {
+    let count_bits: usize = spec::char_count_bits(QrSegmentMode::Byte, version);
+    length < 1 << count_bits && HEADER_BITS + 4 + count_bits + length * 8 <= spec::num_data_codewords(version, ecc) * 8
+}
+
+
+// Returns the data codewords of one symbol: the structured append header, the part as a byte
+// segment, the terminator and the pad bytes that fill the symbol's capacity.
+fn codewords(part: &[u8], index: usize, count: usize, parity: u8, version: u8, ecc: QrCodeEcc) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    spec::append_bits(&mut bits, 0b0011, 4);
+    spec::append_bits(&mut bits, index as u32, 4);
+    spec::append_bits(&mut bits, count as u32 - 1, 4);
+    spec::append_bits(&mut bits, parity as u32, 8);
+    spec::append_bits(&mut bits, 0b0100, 4);
+    spec::append_bits(&mut bits, part.len() as u32, spec::char_count_bits(QrSegmentMode::Byte, version));
+    for &b in part {
+        spec::append_bits(&mut bits, b as u32, 8);
+    }
+
+    spec::pad_codewords(bits, 4, spec::num_data_codewords(version, ecc))
+}
@@ -0,0 +1,133 @@
+use base64::Engine;
+use image::GrayImage;
+use std::io::{self, IsTerminal};
+
+
+// When image outputs for terminals are used instead of falling back to text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphicsMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl std::str::FromStr for GraphicsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(GraphicsMode::Auto),
+            "always" => Ok(GraphicsMode::Always),
+            "never" => Ok(GraphicsMode::Never),
+            _ => Err(format!("Unknown graphics mode: {}. Use auto, always or never", s)),
+        }
+    }
+}
+
+
+// Image protocols a terminal may understand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Sixel,
+    Kitty,
+    ITerm2,
+}
+
+impl Protocol {
+    // Guesses from the environment whether the terminal shows images of this protocol.
+    // Querying the terminal itself would need raw mode, so this only reads TERM and
+    // TERM_PROGRAM. Output that is piped somewhere is assumed to be wanted as is.
+    fn detected(self) -> bool {
+        if !io::stdout().is_terminal() {
+            return true;
+        }
+        let term: String = std::env::var("TERM").unwrap_or_default().to_lowercase();
+        let program: String = std::env::var("TERM_PROGRAM").unwrap_or_default().to_lowercase();
+        match self {
+            Protocol::Sixel => ["mlterm", "foot", "contour", "yaft", "sixel"].iter().any(|name| term.contains(name))
+                || ["wezterm", "mintty", "iterm.app"].contains(&program.as_str()),
+            Protocol::Kitty => term.contains("kitty") || std::env::var_os("KITTY_WINDOW_ID").is_some()
+                || ["wezterm", "ghostty"].contains(&program.as_str()),
+            Protocol::ITerm2 => ["iterm.app", "wezterm", "vscode"].contains(&program.as_str())
+                || std::env::var_os("LC_TERMINAL").is_some_and(|name| name == "iTerm2"),
+        }
+    }
+}
+
+
+// Returns whether to emit the image, or else the text fallback, given the --graphics mode.
+pub fn use_graphics(protocol: Protocol, mode: GraphicsMode) -> bool {
+    match mode {
+        GraphicsMode::Always => true,
+        GraphicsMode::Never => false,
+        GraphicsMode::Auto => protocol.detected(),
+    }
+}
+
+
+// Encodes a black and white image as a Sixel sequence. Each band of six pixel rows is
+// painted once per color, with repeated columns run-length encoded.
+pub fn to_sixel_string(image: &GrayImage) -> String {
+    let (width, height) = image.dimensions();
+    // Two registers, dark and light, with RGB given in percent
+    let mut result: String = format!("\x1bPq\"1;1;{};{}#0;2;0;0;0#1;2;100;100;100", width, height);
+
+    for band in (0..height).step_by(6) {
+        for (register, dark) in [(0, true), (1, false)] {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    (0..6)
+                        .filter(|dy| band + dy < height && (image.get_pixel(x, band + dy).0[0] < 128) == dark)
+                        .fold(0u8, |bits, dy| bits | 1 << dy)
+                })
+                .collect();
+            result += &format!("#{}", register);
+            push_runs(&mut result, &sixels);
+            // Back to the start of the band for the next color
+            result.push('$');
+        }
+        result.push('-');
+    }
+    result += "\x1b\\";
+    result
+}
+
+
+// Appends sixel characters, writing runs of more than three as !count followed by the character.
+fn push_runs(result: &mut String, sixels: &[u8]) {
+    let mut i: usize = 0;
+    while i < sixels.len() {
+        let run: usize = sixels[i..].iter().take_while(|&&sixel| sixel == sixels[i]).count();
+        let c: char = (63 + sixels[i]) as char;
+        if run > 3 {
+            result.push_str(&format!("!{}{}", run, c));
+        } else {
+            result.extend(std::iter::repeat_n(c, run));
+        }
+        i += run;
+    }
+}
+
+
+// Wraps a PNG image in Kitty graphics protocol escapes. The protocol limits each escape to
+// 4096 bytes of base64, so longer images are sent in chunks, all but the last marked m=1.
+pub fn to_kitty_string(png: &[u8]) -> String {
+    let encoded: String = base64::engine::general_purpose::STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    let mut result = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        // Only the first chunk carries the format (PNG) and the action (transmit and display)
+        let keys: &str = if i == 0 { "f=100,a=T," } else { "" };
+        let more: u8 = u8::from(i + 1 < chunks.len());
+        result += &format!("\x1b_G{}m={};{}\x1b\\", keys, more, String::from_utf8_lossy(chunk));
+    }
+    result
+}
+
+
+// Wraps a PNG image in the iTerm2 inline image escape, OSC 1337 File=, which carries
+// the whole image in one base64 argument.
+pub fn to_iterm2_string(png: &[u8]) -> String {
+    format!("\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+        png.len(), base64::engine::general_purpose::STANDARD.encode(png))
+}
@@ -0,0 +1,46 @@
+// Diagnostic logging for both binaries. With the tracing feature the macros forward to
+// the tracing crate and RUST_LOG picks what is printed; without it they expand to nothing.
+
+
+// Sets up logging to stderr, filtered by RUST_LOG. Closing spans report their duration.
+#[cfg(feature = "tracing")]
+pub fn init() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn init() {}
+
+
+// Enters a span named after a pipeline stage until the end of the enclosing block.
+#[cfg(feature = "tracing")]
+macro_rules! stage {
+    ($($arg:tt)*) => {
+        let _stage = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! stage {
+    ($($arg:tt)*) => {};
+}
+
+
+// Logs a decision or intermediate result inside a stage.
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {};
+}
+
+pub(crate) use {debug, stage};
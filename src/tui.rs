@@ -0,0 +1,108 @@
+use crossterm::{cursor, execute, queue, style::Print, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use qrcodegen::{QrCode, QrCodeEcc, QrSegment};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{Cli, Glyphs, OutputType, check_overwrite, segment, spec, to_text_string, write_symbol};
+
+
+// Settings the user can change while the preview is running.
+struct Preview {
+    ecc: QrCodeEcc,
+    border: i32,
+    invert: bool,
+    status: String,
+}
+
+
+// Shows the QR code for the given segments in the terminal and lets the user
+// cycle the ECC level, toggle inversion and change the border before saving it as PNG.
+pub fn run(segs: &[QrSegment], ecc: QrCodeEcc, border: i32, args: &Cli, output_file: &Path) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let mut preview = Preview { ecc, border, invert: args.invert, status: String::new() };
+    let result = event_loop(&mut stdout, segs, &mut preview, args, output_file);
+
+    // Always restore the terminal, even if drawing failed
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+
+fn event_loop(stdout: &mut io::Stdout, segs: &[QrSegment], preview: &mut Preview, args: &Cli, output_file: &Path) -> io::Result<()> {
+    loop {
+        let qr: Option<QrCode> = QrCode::encode_segments(segs, preview.ecc).ok();
+        draw(stdout, qr.as_ref(), preview)?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+            _ => continue,
+        };
+        preview.status.clear();
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('e') => preview.ecc = next_ecc(preview.ecc),
+            KeyCode::Char('i') => preview.invert = !preview.invert,
+            KeyCode::Char('+') | KeyCode::Char('=') => preview.border += 1,
+            KeyCode::Char('-') => preview.border = (preview.border - 1).max(0),
+            KeyCode::Char('s') => {
+                let Some(qr) = &qr else { continue };
+                preview.status = match check_overwrite(output_file, args.force)
+                    .and_then(|_| save(qr, segs, preview, args, output_file)) {
+                    Ok(()) => format!("Saved {}", output_file.display()),
+                    Err(e) => e,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+
+// Writes the code as PNG the way qr-gen does with the same options, with the border and inversion of the preview.
+fn save(qr: &QrCode, segs: &[QrSegment], preview: &Preview, args: &Cli, output_file: &Path) -> Result<(), String> {
+    let payload: Vec<u8> = segment::decoded_bytes(segs);
+    let args = Cli { output_type: OutputType::PNG, output_file: Some(output_file.to_path_buf()), border_width: preview.border as f64, invert: preview.invert, ..args.clone() };
+    write_symbol(qr, &String::from_utf8_lossy(&payload), &payload, &args, output_file, false, &mut None).map_err(|e| e.to_string())
+}
+
+
+// Redraws the whole screen: the code, its settings and the key help.
+fn draw(stdout: &mut io::Stdout, qr: Option<&QrCode>, preview: &Preview) -> io::Result<()> {
+    queue!(stdout, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+
+    let mut lines: Vec<String> = match qr {
+        Some(qr) => {
+            let mut lines: Vec<String> = to_text_string(qr, preview.border, preview.invert, Glyphs::Block).lines().map(String::from).collect();
+            lines.push(format!("Version {}  ECC {}  Border {}  Invert {}",
+                qr.version().value(), spec::ecc_letter(preview.ecc), preview.border, if preview.invert { "on" } else { "off" }));
+            lines
+        }
+        None => vec![format!("The data does not fit at ECC {}.", spec::ecc_letter(preview.ecc))],
+    };
+    lines.push("e: cycle ECC  i: invert  +/-: border  s: save PNG  q: quit".to_string());
+    if !preview.status.is_empty() {
+        lines.push(preview.status.clone());
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+    }
+    stdout.flush()
+}
+
+
+// Returns the next error correction level, wrapping from High back to Low.
+fn next_ecc(ecc: QrCodeEcc) -> QrCodeEcc {
+    match ecc {
+        QrCodeEcc::Low => QrCodeEcc::Medium,
+        QrCodeEcc::Medium => QrCodeEcc::Quartile,
+        QrCodeEcc::Quartile => QrCodeEcc::High,
+        QrCodeEcc::High => QrCodeEcc::Low,
+    }
+}